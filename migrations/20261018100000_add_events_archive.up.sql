@@ -0,0 +1,11 @@
+create table events_archive (
+  id int primary key,
+  user_id varchar(255),
+  device_id int,
+  post_id varchar(255) not null,
+  kind enum ('view', 'like', 'share') not null,
+  timestamp timestamp not null,
+  archived_at timestamp not null default current_timestamp
+);
+
+create index idx_events_archive_post_id on events_archive (post_id);
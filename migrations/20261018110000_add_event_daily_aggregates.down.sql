@@ -0,0 +1,3 @@
+drop index idx_events_kind_timestamp on events;
+
+drop table event_daily_aggregates;
@@ -0,0 +1,9 @@
+create table event_daily_aggregates (
+  post_id varchar(255) not null,
+  day date not null,
+  kind enum ('view', 'like', 'share') not null,
+  total bigint not null,
+  primary key (post_id, day, kind)
+);
+
+create index idx_events_kind_timestamp on events (kind, timestamp);
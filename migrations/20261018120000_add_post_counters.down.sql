@@ -0,0 +1,3 @@
+drop index idx_events_post_id on events;
+
+drop table post_counters;
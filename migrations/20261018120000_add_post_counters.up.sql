@@ -0,0 +1,21 @@
+create table post_counters (
+  post_id varchar(255) primary key,
+  views bigint not null default 0,
+  likes bigint not null default 0,
+  shares bigint not null default 0
+);
+
+insert into post_counters (post_id, views, likes, shares)
+select
+  post_id,
+  sum(if(kind = 'view', n, 0)),
+  sum(if(kind = 'like', n, 0)),
+  sum(if(kind = 'share', n, 0))
+from (
+  select post_id, kind, 1 as n from events
+  union all
+  select post_id, kind, total as n from event_daily_aggregates
+) as e
+group by post_id;
+
+create index idx_events_post_id on events (post_id);
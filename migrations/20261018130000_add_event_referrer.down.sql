@@ -0,0 +1,4 @@
+drop index idx_events_post_id_timestamp on events;
+
+alter table events_archive drop column referrer;
+alter table events drop column referrer;
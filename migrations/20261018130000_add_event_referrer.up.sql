@@ -0,0 +1,4 @@
+alter table events add column referrer varchar(255) null;
+alter table events_archive add column referrer varchar(255) null;
+
+create index idx_events_post_id_timestamp on events (post_id, timestamp);
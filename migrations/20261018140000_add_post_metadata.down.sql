@@ -0,0 +1,2 @@
+drop table post_tags;
+drop table post_metadata;
@@ -0,0 +1,16 @@
+create table post_metadata (
+  post_id varchar(255) primary key,
+  -- Null once the articles service no longer knows the post.
+  author_id varchar(255) null,
+  published_at timestamp null,
+  refreshed_at timestamp not null,
+  index idx_post_metadata_author_id (author_id),
+  index idx_post_metadata_refreshed_at (refreshed_at)
+);
+
+create table post_tags (
+  post_id varchar(255) not null,
+  tag varchar(255) not null,
+  primary key (post_id, tag),
+  index idx_post_tags_tag (tag)
+);
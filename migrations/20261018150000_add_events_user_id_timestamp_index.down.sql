@@ -0,0 +1 @@
+drop index idx_events_user_id_timestamp on events;
@@ -0,0 +1 @@
+create index idx_events_user_id_timestamp on events (user_id, timestamp);
@@ -0,0 +1,6 @@
+create table related_posts (
+  post_id varchar(255) not null,
+  related_post_id varchar(255) not null,
+  score double not null,
+  primary key (post_id, related_post_id)
+);
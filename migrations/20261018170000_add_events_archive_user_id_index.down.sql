@@ -0,0 +1 @@
+drop index idx_events_archive_user_id on events_archive;
@@ -0,0 +1 @@
+create index idx_events_archive_user_id on events_archive (user_id);
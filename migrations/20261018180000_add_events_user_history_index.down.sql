@@ -0,0 +1 @@
+drop index idx_events_user_id_post_id_kind_id on events;
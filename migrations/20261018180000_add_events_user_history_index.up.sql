@@ -0,0 +1,3 @@
+-- Serves the history of a user, which groups their events by post and kind
+-- and orders the groups by their latest event.
+create index idx_events_user_id_post_id_kind_id on events (user_id, post_id, kind, id);
@@ -0,0 +1,239 @@
+use std::{sync::Arc, time::Duration};
+
+use chrono::Utc;
+use futures_util::{StreamExt, stream};
+use tokio::task::JoinHandle;
+
+use crate::{db::EventRepository, posts::PostsApi};
+
+/// How many posts are fetched from the articles service at once.
+const CONCURRENCY: usize = 8;
+
+/// How the local copy of post metadata, used to aggregate engagement by tag
+/// and author, is kept up to date.
+#[derive(Debug, Clone)]
+pub struct CatalogPolicy {
+    pub interval: Duration,
+    /// Metadata older than this is fetched again.
+    pub max_age: chrono::Duration,
+    pub batch_size: u32,
+}
+
+impl Default for CatalogPolicy {
+    fn default() -> Self {
+        Self {
+            interval: Duration::from_secs(10 * 60),
+            max_age: chrono::Duration::days(1),
+            batch_size: 100,
+        }
+    }
+}
+
+/// Fetches the metadata of posts with events that are missing or stale, in
+/// batches, returning how many posts were refreshed.
+///
+/// Stops at the first batch with a failed lookup, so that posts the articles
+/// service cannot answer for are retried on the next run rather than forever.
+/// Staleness is judged once, at the start, so each post is fetched at most
+/// once per run however short `policy.max_age` is.
+pub async fn refresh(
+    repo: &dyn EventRepository,
+    posts: &dyn PostsApi,
+    policy: &CatalogPolicy,
+) -> anyhow::Result<u64> {
+    let mut refreshed = 0;
+    let stale_before = Utc::now() - policy.max_age;
+
+    loop {
+        let post_ids = repo
+            .find_stale_post_metadata(stale_before, policy.batch_size)
+            .await?;
+
+        let batch_len = post_ids.len();
+
+        let results: Vec<_> = stream::iter(post_ids)
+            .map(|post_id| async move {
+                let result = posts.fetch_post(&post_id).await;
+                (post_id, result)
+            })
+            .buffer_unordered(CONCURRENCY)
+            .collect()
+            .await;
+
+        let mut failed = false;
+        for (post_id, result) in results {
+            match result {
+                Ok(post) => {
+                    repo.save_post_metadata(&post_id, post.as_ref()).await?;
+                    refreshed += 1;
+                }
+                Err(e) => {
+                    tracing::warn!("Failed to fetch post {post_id}: {e:?}");
+                    failed = true;
+                }
+            }
+        }
+
+        if failed || batch_len < policy.batch_size as usize {
+            break;
+        }
+    }
+
+    Ok(refreshed)
+}
+
+/// Spawns a task refreshing post metadata every `policy.interval`.
+pub fn spawn(
+    repo: Arc<dyn EventRepository>,
+    posts: Arc<dyn PostsApi>,
+    policy: CatalogPolicy,
+) -> JoinHandle<()> {
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(policy.interval);
+
+        loop {
+            interval.tick().await;
+
+            match refresh(repo.as_ref(), posts.as_ref(), &policy).await {
+                Ok(0) => {}
+                Ok(count) => tracing::info!("Refreshed the metadata of {count} posts"),
+                Err(e) => tracing::error!("Failed to refresh post metadata: {e:?}"),
+            }
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{Days, NaiveDate};
+
+    use super::*;
+    use crate::{
+        db::NewEvent,
+        domain::EventKind,
+        posts::{MockPostsClient, Post},
+        testing::MemoryRepository,
+    };
+
+    fn event(post_id: &str, kind: EventKind) -> NewEvent {
+        NewEvent {
+            post_id: post_id.to_owned(),
+            kind,
+            device: None,
+            user_id: None,
+            referrer: None,
+            timestamp: Utc::now(),
+        }
+    }
+
+    async fn repo_with_posts(post_ids: &[&str]) -> MemoryRepository {
+        let repo = MemoryRepository::default();
+        let events: Vec<NewEvent> = post_ids
+            .iter()
+            .map(|post_id| event(post_id, EventKind::View))
+            .collect();
+        repo.create_events(&events).await.unwrap();
+        repo
+    }
+
+    fn policy(max_age: chrono::Duration) -> CatalogPolicy {
+        CatalogPolicy {
+            max_age,
+            batch_size: 2,
+            ..Default::default()
+        }
+    }
+
+    fn today() -> (NaiveDate, NaiveDate) {
+        let today = Utc::now().date_naive();
+        (today, today.checked_add_days(Days::new(1)).unwrap())
+    }
+
+    #[tokio::test]
+    async fn refreshes_missing_metadata_in_batches() {
+        let repo = repo_with_posts(&["post-00001", "post-00002", "post-00003"]).await;
+        let policy = policy(chrono::Duration::days(1));
+
+        assert_eq!(refresh(&repo, &MockPostsClient, &policy).await.unwrap(), 3);
+        // Nothing is stale right after.
+        assert_eq!(refresh(&repo, &MockPostsClient, &policy).await.unwrap(), 0);
+    }
+
+    #[tokio::test]
+    async fn fetches_each_post_once_per_run() {
+        let repo = repo_with_posts(&["post-00001", "post-00002", "post-00003"]).await;
+        // Whatever is saved is stale again right away.
+        let policy = policy(chrono::Duration::zero());
+
+        assert_eq!(refresh(&repo, &MockPostsClient, &policy).await.unwrap(), 3);
+        assert!(
+            repo.metadata_saves
+                .lock()
+                .unwrap()
+                .values()
+                .all(|&n| n == 1)
+        );
+    }
+
+    #[tokio::test]
+    async fn stops_at_a_failed_lookup() {
+        let repo = repo_with_posts(&["post-00001", "unavailable", "post-00003"]).await;
+        let policy = CatalogPolicy {
+            batch_size: 10,
+            ..Default::default()
+        };
+
+        assert_eq!(refresh(&repo, &MockPostsClient, &policy).await.unwrap(), 2);
+        assert!(!repo.metadata.lock().unwrap().contains_key("unavailable"));
+    }
+
+    #[tokio::test]
+    async fn ranks_engagement_by_tag_and_author() {
+        let repo = MemoryRepository::default();
+        repo.create_events(&[
+            event("a", EventKind::View),
+            event("a", EventKind::Like),
+            event("b", EventKind::View),
+            event("c", EventKind::Share),
+        ])
+        .await
+        .unwrap();
+
+        let post = |author_id: &str, tags: &[&str]| Post {
+            id: String::new(),
+            author_id: author_id.to_owned(),
+            tags: tags.iter().map(|tag| tag.to_string()).collect(),
+            published_at: None,
+        };
+        repo.save_post_metadata("a", Some(&post("ana", &["rust", "web"])))
+            .await
+            .unwrap();
+        repo.save_post_metadata("b", Some(&post("bob", &["web"])))
+            .await
+            .unwrap();
+        // Posts the articles service no longer knows count for nobody.
+        repo.save_post_metadata("c", None).await.unwrap();
+
+        let (from, to) = today();
+        let tags = repo.find_tag_engagement(from, to, 10).await.unwrap();
+        let tags: Vec<_> = tags
+            .iter()
+            .map(|t| (t.tag.as_str(), t.summary.views, t.summary.likes))
+            .collect();
+        assert_eq!(tags, [("web", 2, 1), ("rust", 1, 1)]);
+
+        let authors = repo.find_author_engagement(from, to, 1).await.unwrap();
+        assert_eq!(authors.len(), 1);
+        assert_eq!(authors[0].author_id, "ana");
+        assert_eq!(authors[0].summary.likes, 1);
+
+        // Days outside the range are left out.
+        let yesterday = from.checked_sub_days(Days::new(1)).unwrap();
+        assert!(
+            repo.find_tag_engagement(yesterday, from, 10)
+                .await
+                .unwrap()
+                .is_empty()
+        );
+    }
+}
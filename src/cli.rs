@@ -0,0 +1,312 @@
+use std::io::{self, Write};
+
+use chrono::{DateTime, NaiveDate, Utc};
+use clap::{Parser, Subcommand};
+use futures_util::TryStreamExt;
+
+use crate::{
+    config::{Config, ConfigArgs, Usage},
+    db::{EventRepository, MigrationReport, MySql},
+    domain::EventKind,
+    http::export::ExportFormat,
+    retention,
+    telemetry::LogOutput,
+};
+
+#[derive(Debug, Parser)]
+#[command(version, about)]
+pub struct Cli {
+    #[command(flatten)]
+    pub config: ConfigArgs,
+
+    #[command(subcommand)]
+    pub command: Option<Command>,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum Command {
+    /// Serve the HTTP API. This is the default.
+    Serve,
+
+    /// Manage the database schema.
+    #[command(subcommand)]
+    Migrate(MigrateCommand),
+
+    /// Recompute the event counters of one post, or of every post.
+    RebuildCounters {
+        #[arg(long)]
+        post_id: Option<String>,
+    },
+
+    /// Roll up events older than a date into daily aggregates and delete them.
+    Purge {
+        /// A date (`2026-01-31`) or RFC 3339 timestamp.
+        #[arg(long, value_parser = parse_before)]
+        before: DateTime<Utc>,
+
+        /// Only purge events of this kind. Can be repeated; defaults to all.
+        #[arg(long, value_parser = parse_kind)]
+        kind: Vec<EventKind>,
+    },
+
+    /// Write every event of a post to stdout.
+    Export {
+        #[arg(long)]
+        post_id: String,
+
+        #[arg(long, value_enum, default_value_t)]
+        format: ExportFormat,
+    },
+
+    /// Validate the configuration and exit.
+    CheckConfig,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum MigrateCommand {
+    /// Apply every pending migration.
+    Up,
+
+    /// Revert the last applied migrations.
+    Down {
+        #[arg(long, default_value_t = 1)]
+        steps: usize,
+    },
+
+    /// List migrations and whether they are applied.
+    Status,
+}
+
+impl Command {
+    pub fn log_output(&self) -> LogOutput {
+        match self {
+            Self::Serve => LogOutput::Stdout,
+            _ => LogOutput::Stderr,
+        }
+    }
+
+    pub fn config_usage(&self) -> Usage {
+        match self {
+            Self::Serve | Self::CheckConfig => Usage::Serve,
+            Self::Purge { .. } => Usage::Purge,
+            Self::Migrate(_) | Self::RebuildCounters { .. } | Self::Export { .. } => {
+                Usage::Database
+            }
+        }
+    }
+}
+
+fn parse_before(value: &str) -> Result<DateTime<Utc>, String> {
+    if let Ok(date) = NaiveDate::parse_from_str(value, "%Y-%m-%d") {
+        return Ok(date.and_hms_opt(0, 0, 0).unwrap().and_utc());
+    }
+
+    DateTime::parse_from_rfc3339(value)
+        .map(|timestamp| timestamp.to_utc())
+        .map_err(|_| format!("Expected a date or RFC 3339 timestamp, got `{value}`"))
+}
+
+fn parse_kind(value: &str) -> Result<EventKind, String> {
+    match value {
+        "view" => Ok(EventKind::View),
+        "like" => Ok(EventKind::Like),
+        "share" => Ok(EventKind::Share),
+        other => Err(format!("Unknown event kind: {other}")),
+    }
+}
+
+/// Runs a maintenance command against the database.
+pub async fn run(command: Command, config: &Config) -> anyhow::Result<()> {
+    let mysql = MySql::connect(&config.database).await?;
+
+    let result = match command {
+        Command::Migrate(command) => migrate(&mysql, command, config).await,
+        Command::RebuildCounters { post_id } => {
+            let count = mysql.rebuild_counters(post_id.as_deref()).await?;
+            println!("Rebuilt the counters of {count} posts");
+            Ok(())
+        }
+        Command::Purge { before, kind } => {
+            purge(&mysql, before, kind, config.retention.batch_size).await
+        }
+        Command::Export { post_id, format } => {
+            export(&mysql, &post_id, format, &mut io::stdout().lock()).await
+        }
+        Command::Serve | Command::CheckConfig => unreachable!("handled by main"),
+    };
+
+    mysql.close().await;
+    result
+}
+
+async fn migrate(mysql: &MySql, command: MigrateCommand, config: &Config) -> anyhow::Result<()> {
+    let lock_timeout = config.migration_lock_timeout();
+
+    match command {
+        MigrateCommand::Up => {
+            mysql.migrate(lock_timeout).await?;
+            println!("Database is up to date");
+        }
+        MigrateCommand::Down { steps } => {
+            for version in mysql.undo_migrations(steps, lock_timeout).await? {
+                println!("Reverted {version}");
+            }
+        }
+        MigrateCommand::Status => {
+            let report = MigrationReport::from(mysql.migration_status().await?);
+
+            for migration in &report.migrations {
+                let state = if migration.applied {
+                    "applied"
+                } else {
+                    "pending"
+                };
+                println!(
+                    "{} {:<8} {}",
+                    migration.version, state, migration.description
+                );
+            }
+
+            println!("{} applied, {} pending", report.applied, report.pending);
+        }
+    }
+
+    Ok(())
+}
+
+async fn purge(
+    repo: &dyn EventRepository,
+    before: DateTime<Utc>,
+    kinds: Vec<EventKind>,
+    batch_size: u32,
+) -> anyhow::Result<()> {
+    let kinds = if kinds.is_empty() {
+        vec![EventKind::View, EventKind::Like, EventKind::Share]
+    } else {
+        kinds
+    };
+
+    let mut purged = 0;
+    for kind in kinds {
+        purged += retention::purge_before(repo, kind, before, batch_size).await?;
+    }
+
+    println!("Rolled up {purged} events older than {before}");
+    Ok(())
+}
+
+async fn export(
+    repo: &dyn EventRepository,
+    post_id: &str,
+    format: ExportFormat,
+    out: &mut impl Write,
+) -> anyhow::Result<()> {
+    // Streamed, as a post can have more events than fit in memory.
+    let mut events = repo.stream_post_events(post_id.to_owned());
+
+    match format {
+        ExportFormat::Json => {
+            write!(out, "[")?;
+            let mut first = true;
+            while let Some(event) = events.try_next().await? {
+                let sep = if first { "" } else { "," };
+                write!(out, "{sep}\n  {}", serde_json::to_string(&event)?)?;
+                first = false;
+            }
+            writeln!(out, "\n]")?;
+        }
+        ExportFormat::Csv => {
+            let mut writer = csv::Writer::from_writer(out);
+            while let Some(event) = events.try_next().await? {
+                writer.serialize(event)?;
+            }
+            writer.flush()?;
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{db::NewEvent, testing::MemoryRepository};
+
+    fn event(post_id: &str) -> NewEvent {
+        NewEvent {
+            post_id: post_id.to_owned(),
+            kind: EventKind::View,
+            device: None,
+            user_id: None,
+            referrer: None,
+            timestamp: Utc::now(),
+        }
+    }
+
+    #[test]
+    fn parses_dates_and_timestamps() {
+        assert_eq!(
+            parse_before("2026-01-31").unwrap().to_rfc3339(),
+            "2026-01-31T00:00:00+00:00"
+        );
+        assert_eq!(
+            parse_before("2026-01-31T12:00:00-03:00")
+                .unwrap()
+                .to_rfc3339(),
+            "2026-01-31T15:00:00+00:00"
+        );
+        assert!(parse_before("yesterday").is_err());
+    }
+
+    #[test]
+    fn parses_subcommands() {
+        let cli = Cli::parse_from([
+            "engagement",
+            "purge",
+            "--before",
+            "2026-01-01",
+            "--kind",
+            "view",
+        ]);
+        assert!(matches!(
+            cli.command,
+            Some(Command::Purge { kind, .. }) if kind == [EventKind::View]
+        ));
+
+        let cli = Cli::parse_from(["engagement", "migrate", "down", "--steps", "2"]);
+        assert!(matches!(
+            cli.command,
+            Some(Command::Migrate(MigrateCommand::Down { steps: 2 }))
+        ));
+
+        assert!(Cli::parse_from(["engagement"]).command.is_none());
+    }
+
+    #[tokio::test]
+    async fn exports_the_events_of_a_post() {
+        let repo = MemoryRepository::default();
+        repo.create_events(&[event("a"), event("b"), event("a")])
+            .await
+            .unwrap();
+
+        let mut out = Vec::new();
+        export(&repo, "a", ExportFormat::Json, &mut out)
+            .await
+            .unwrap();
+        let events: Vec<serde_json::Value> = serde_json::from_slice(&out).unwrap();
+        assert_eq!(events.len(), 2);
+        assert_eq!(events[1]["id"], 3);
+
+        let mut out = Vec::new();
+        export(&repo, "a", ExportFormat::Csv, &mut out)
+            .await
+            .unwrap();
+        assert_eq!(String::from_utf8(out).unwrap().lines().count(), 3);
+
+        let mut out = Vec::new();
+        export(&repo, "c", ExportFormat::Json, &mut out)
+            .await
+            .unwrap();
+        assert_eq!(serde_json::from_slice::<Vec<()>>(&out).unwrap(), []);
+    }
+}
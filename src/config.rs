@@ -0,0 +1,997 @@
+use std::{env, fmt, fs, path::PathBuf, str::FromStr, time::Duration};
+
+use reqwest::{
+    Method, Url,
+    header::{HeaderName, HeaderValue},
+};
+use serde::Deserialize;
+use thiserror::Error;
+
+use crate::{
+    catalog::CatalogPolicy,
+    domain::{ErasureMode, PurgeMode},
+    http::auth::ApiKeyStore,
+    ingest::IngestConfig,
+    jwt::JwtUsersClient,
+    recommendations::RecommendationPolicy,
+    retention::RetentionPolicy,
+};
+
+/// Settings that can be given on the command line, taking precedence over the
+/// configuration file and the environment.
+#[derive(Debug, Clone, Default, clap::Args)]
+pub struct ConfigArgs {
+    /// TOML configuration file. Can also be set with `CONFIG_FILE`.
+    #[arg(long, short, global = true)]
+    pub config: Option<PathBuf>,
+
+    /// Address to listen on.
+    #[arg(long, global = true)]
+    pub host: Option<String>,
+
+    /// Port to listen on.
+    #[arg(long, global = true)]
+    pub port: Option<u16>,
+
+    /// MySQL connection URL.
+    #[arg(long, global = true)]
+    pub database_url: Option<String>,
+}
+
+/// Every invalid setting found while loading the configuration.
+#[derive(Debug, Error)]
+pub struct ConfigError(pub Vec<String>);
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Invalid configuration:")?;
+        for error in &self.0 {
+            write!(f, "\n  - {error}")?;
+        }
+        Ok(())
+    }
+}
+
+/// Which sections of the configuration a command uses, and so validates.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Usage {
+    /// Serving the API needs every section.
+    Serve,
+    /// Maintenance commands only need the database.
+    Database,
+    /// Purging also needs the retention batch size.
+    Purge,
+}
+
+/// Service configuration. Values are read from the defaults, then an optional
+/// TOML file, then environment variables and finally command line flags.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub http: HttpConfig,
+    pub cors: CorsConfig,
+    pub database: DatabaseConfig,
+    pub users: UsersConfig,
+    pub articles: ArticlesConfig,
+    pub auth: AuthConfig,
+    pub ingest: IngestSettings,
+    pub retention: RetentionSettings,
+    pub catalog: CatalogSettings,
+    pub recommendations: RecommendationSettings,
+    pub privacy: PrivacyConfig,
+    pub features: Features,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct HttpConfig {
+    pub host: String,
+    pub port: u16,
+    /// How long in-flight requests may take to finish on shutdown.
+    pub shutdown_timeout_secs: u64,
+}
+
+impl Default for HttpConfig {
+    fn default() -> Self {
+        Self {
+            host: "0.0.0.0".to_owned(),
+            port: 8080,
+            shutdown_timeout_secs: 30,
+        }
+    }
+}
+
+/// CORS policies of the public and private routers.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct CorsConfig {
+    pub public: CorsPolicy,
+    pub private: CorsPolicy,
+}
+
+impl Default for CorsConfig {
+    fn default() -> Self {
+        Self {
+            public: CorsPolicy {
+                enabled: true,
+                ..Default::default()
+            },
+            private: CorsPolicy::default(),
+        }
+    }
+}
+
+/// Which cross-origin requests a router accepts. Empty lists allow any value.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct CorsPolicy {
+    pub enabled: bool,
+    pub origins: Vec<String>,
+    pub methods: Vec<String>,
+    pub headers: Vec<String>,
+    pub credentials: bool,
+}
+
+impl CorsPolicy {
+    fn validate(&self, name: &str, errors: &mut Vec<String>) {
+        if !self.enabled {
+            return;
+        }
+
+        for origin in &self.origins {
+            if Url::parse(origin).is_err() || HeaderValue::from_str(origin).is_err() {
+                errors.push(format!("cors.{name}.origins: invalid origin `{origin}`"));
+            }
+        }
+        for method in &self.methods {
+            if Method::from_str(method).is_err() {
+                errors.push(format!("cors.{name}.methods: invalid method `{method}`"));
+            }
+        }
+        for header in &self.headers {
+            if HeaderName::from_str(header).is_err() {
+                errors.push(format!("cors.{name}.headers: invalid header `{header}`"));
+            }
+        }
+
+        // Browsers reject credentials alongside wildcards.
+        if self.credentials
+            && (self.origins.is_empty() || self.methods.is_empty() || self.headers.is_empty())
+        {
+            errors.push(format!(
+                "cors.{name}.credentials requires explicit origins, methods and headers"
+            ));
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct DatabaseConfig {
+    pub url: String,
+    pub max_connections: u32,
+    /// How long to wait for a free connection before failing.
+    pub acquire_timeout_secs: u64,
+    /// Apply pending migrations when serving.
+    pub auto_migrate: bool,
+    /// How long to wait for another instance that is migrating.
+    pub migration_lock_timeout_secs: u64,
+}
+
+impl Default for DatabaseConfig {
+    fn default() -> Self {
+        Self {
+            url: String::new(),
+            max_connections: 5,
+            acquire_timeout_secs: 30,
+            auto_migrate: false,
+            migration_lock_timeout_secs: 60,
+        }
+    }
+}
+
+/// How user tokens are validated.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum UsersAuth {
+    /// Call `/introspect` on the users service.
+    #[default]
+    Introspect,
+    /// Verify JWTs locally.
+    Jwt,
+}
+
+impl FromStr for UsersAuth {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "introspect" => Ok(Self::Introspect),
+            "jwt" => Ok(Self::Jwt),
+            other => Err(format!("Unknown users auth mode: {other}")),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct UsersConfig {
+    pub url: Option<String>,
+    pub auth: UsersAuth,
+    pub timeout_secs: u64,
+    pub jwt_secret: Option<String>,
+    pub jwt_jwks_file: Option<PathBuf>,
+    pub jwt_audience: Vec<String>,
+    pub jwt_user_id_claim: Option<String>,
+}
+
+impl Default for UsersConfig {
+    fn default() -> Self {
+        Self {
+            url: None,
+            auth: UsersAuth::default(),
+            timeout_secs: 10,
+            jwt_secret: None,
+            jwt_jwks_file: None,
+            jwt_audience: Vec::new(),
+            jwt_user_id_claim: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ArticlesConfig {
+    pub url: String,
+    pub timeout_secs: u64,
+    /// How long fetched post metadata is reused. Zero disables the cache.
+    pub cache_ttl_secs: u64,
+    pub cache_capacity: usize,
+}
+
+impl Default for ArticlesConfig {
+    fn default() -> Self {
+        Self {
+            url: String::new(),
+            timeout_secs: 10,
+            cache_ttl_secs: 300,
+            cache_capacity: 10_000,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct AuthConfig {
+    /// JSON file with the named API keys for the private endpoints.
+    pub api_keys_file: Option<PathBuf>,
+    /// Tokens accepted as the `internal` key with every scope.
+    pub internal_tokens: Vec<String>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct IngestSettings {
+    pub buffer_size: usize,
+    pub batch_size: usize,
+    pub flush_interval_ms: u64,
+}
+
+impl Default for IngestSettings {
+    fn default() -> Self {
+        let defaults = IngestConfig::default();
+        Self {
+            buffer_size: defaults.capacity,
+            batch_size: defaults.batch_size,
+            flush_interval_ms: defaults.flush_interval.as_millis() as u64,
+        }
+    }
+}
+
+/// Longer retention periods are better expressed as `forever`.
+const MAX_RETENTION_DAYS: u32 = 100 * 366;
+
+/// A number of days to keep raw events for, or `forever`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RetentionDays(pub Option<u32>);
+
+impl FromStr for RetentionDays {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "forever" => Ok(Self(None)),
+            days => days
+                .parse()
+                .map(|days| Self(Some(days)))
+                .map_err(|_| format!("Expected a number of days or `forever`, got `{days}`")),
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for RetentionDays {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        #[derive(Deserialize)]
+        #[serde(untagged)]
+        enum Raw {
+            Days(u32),
+            Text(String),
+        }
+
+        match Raw::deserialize(deserializer)? {
+            Raw::Days(days) => Ok(Self(Some(days))),
+            Raw::Text(text) => text.parse().map_err(serde::de::Error::custom),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct RetentionSettings {
+    pub view_days: RetentionDays,
+    pub like_days: RetentionDays,
+    pub share_days: RetentionDays,
+    pub interval_secs: u64,
+    pub batch_size: u32,
+}
+
+impl Default for RetentionSettings {
+    fn default() -> Self {
+        let defaults = RetentionPolicy::default();
+        let days = |age: Option<chrono::Duration>| RetentionDays(age.map(|a| a.num_days() as u32));
+
+        Self {
+            view_days: days(defaults.views),
+            like_days: days(defaults.likes),
+            share_days: days(defaults.shares),
+            interval_secs: defaults.interval.as_secs(),
+            batch_size: defaults.batch_size,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct CatalogSettings {
+    pub interval_secs: u64,
+    pub max_age_secs: u64,
+    pub batch_size: u32,
+}
+
+impl Default for CatalogSettings {
+    fn default() -> Self {
+        let defaults = CatalogPolicy::default();
+        Self {
+            interval_secs: defaults.interval.as_secs(),
+            max_age_secs: defaults.max_age.num_seconds() as u64,
+            batch_size: defaults.batch_size,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct RecommendationSettings {
+    pub interval_secs: u64,
+    pub window_days: u32,
+    pub max_posts_per_user: usize,
+    pub related_per_post: usize,
+    pub batch_size: u32,
+}
+
+impl Default for RecommendationSettings {
+    fn default() -> Self {
+        let defaults = RecommendationPolicy::default();
+        Self {
+            interval_secs: defaults.interval.as_secs(),
+            window_days: defaults.window.num_days() as u32,
+            max_posts_per_user: defaults.max_posts_per_user,
+            related_per_post: defaults.related_per_post,
+            batch_size: defaults.batch_size,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct PrivacyConfig {
+    pub erasure_mode: ErasureMode,
+    pub post_purge_mode: PurgeMode,
+}
+
+/// Optional parts of the service that can be turned off.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Features {
+    /// Serve the Swagger UI at `/docs`.
+    pub docs: bool,
+    /// Serve Prometheus metrics at `/metrics`.
+    pub metrics: bool,
+    /// Run the retention policy in the background.
+    pub retention: bool,
+    /// Refresh post metadata for the tag and author analytics in the background.
+    pub catalog: bool,
+    /// Compute related posts in the background.
+    pub recommendations: bool,
+    /// Accept post deletions from the articles service at `/webhooks/articles`.
+    pub articles_webhook: bool,
+}
+
+impl Default for Features {
+    fn default() -> Self {
+        Self {
+            docs: true,
+            metrics: true,
+            retention: true,
+            catalog: true,
+            recommendations: true,
+            articles_webhook: true,
+        }
+    }
+}
+
+impl Config {
+    /// Loads the configuration from the process environment, validating the
+    /// sections needed for `usage`.
+    pub fn load(args: &ConfigArgs, usage: Usage) -> Result<Self, ConfigError> {
+        Self::load_from(args, usage, |name| env::var(name).ok())
+    }
+
+    fn load_from(
+        args: &ConfigArgs,
+        usage: Usage,
+        env: impl Fn(&str) -> Option<String>,
+    ) -> Result<Self, ConfigError> {
+        let mut loader = Loader {
+            env: &env,
+            errors: Vec::new(),
+        };
+
+        let file = args
+            .config
+            .clone()
+            .or_else(|| env("CONFIG_FILE").map(PathBuf::from));
+        let mut config = match file {
+            Some(path) => loader.read_file(path),
+            None => Config::default(),
+        };
+
+        loader.apply_env(&mut config);
+
+        if let Some(host) = &args.host {
+            config.http.host = host.clone();
+        }
+        if let Some(port) = args.port {
+            config.http.port = port;
+        }
+        if let Some(url) = &args.database_url {
+            config.database.url = url.clone();
+        }
+
+        config.validate(usage, &mut loader.errors);
+
+        if loader.errors.is_empty() {
+            Ok(config)
+        } else {
+            Err(ConfigError(loader.errors))
+        }
+    }
+
+    fn validate(&self, usage: Usage, errors: &mut Vec<String>) {
+        let mut require_url = |name: &str, var: &str, url: Option<&str>| match url {
+            None | Some("") => errors.push(format!("{name} is required (set {var})")),
+            Some(url) => {
+                if let Err(e) = Url::parse(url) {
+                    errors.push(format!("{name}: invalid URL `{url}`: {e}"));
+                }
+            }
+        };
+
+        require_url("database.url", "DATABASE_URL", Some(&self.database.url));
+
+        let mut positive = vec![(
+            "database.max_connections",
+            self.database.max_connections as u64,
+        )];
+
+        if usage == Usage::Purge {
+            positive.push(("retention.batch_size", self.retention.batch_size as u64));
+        }
+
+        if usage == Usage::Serve {
+            require_url("articles.url", "ARTICLES_URL", Some(&self.articles.url));
+
+            match self.users.auth {
+                UsersAuth::Introspect => {
+                    require_url("users.url", "USERS_URL", self.users.url.as_deref());
+                }
+                UsersAuth::Jwt => match &self.users.jwt_jwks_file {
+                    Some(path) => {
+                        if let Err(e) = JwtUsersClient::from_jwks_file(path) {
+                            errors.push(format!("users.jwt_jwks_file: {e:#}"));
+                        }
+                    }
+                    None if self.users.jwt_secret.is_none() => errors.push(
+                        "users.jwt_secret or users.jwt_jwks_file is required with JWT auth \
+                         (set JWT_SECRET or JWT_JWKS_FILE)"
+                            .to_owned(),
+                    ),
+                    None => {}
+                },
+            }
+
+            if let Err(e) = ApiKeyStore::new(
+                self.auth.api_keys_file.clone(),
+                self.auth.internal_tokens.clone(),
+            ) {
+                errors.push(format!(
+                    "auth: {e:#} (set INTERNAL_SECRET_TOKEN or API_KEYS_FILE)"
+                ));
+            }
+
+            self.cors.public.validate("public", errors);
+            self.cors.private.validate("private", errors);
+
+            positive.extend([
+                ("ingest.buffer_size", self.ingest.buffer_size as u64),
+                ("ingest.batch_size", self.ingest.batch_size as u64),
+                ("ingest.flush_interval_ms", self.ingest.flush_interval_ms),
+                ("retention.interval_secs", self.retention.interval_secs),
+                ("retention.batch_size", self.retention.batch_size as u64),
+                ("catalog.interval_secs", self.catalog.interval_secs),
+                ("catalog.max_age_secs", self.catalog.max_age_secs),
+                ("catalog.batch_size", self.catalog.batch_size as u64),
+                (
+                    "recommendations.interval_secs",
+                    self.recommendations.interval_secs,
+                ),
+                (
+                    "recommendations.window_days",
+                    self.recommendations.window_days as u64,
+                ),
+                (
+                    "recommendations.max_posts_per_user",
+                    self.recommendations.max_posts_per_user as u64,
+                ),
+                (
+                    "recommendations.related_per_post",
+                    self.recommendations.related_per_post as u64,
+                ),
+                (
+                    "recommendations.batch_size",
+                    self.recommendations.batch_size as u64,
+                ),
+            ]);
+
+            let retention_days = [
+                ("retention.view_days", self.retention.view_days),
+                ("retention.like_days", self.retention.like_days),
+                ("retention.share_days", self.retention.share_days),
+            ];
+            for (name, days) in retention_days {
+                if days.0.is_some_and(|days| days > MAX_RETENTION_DAYS) {
+                    errors.push(format!("{name} must be at most {MAX_RETENTION_DAYS}"));
+                }
+            }
+        }
+
+        for (name, value) in positive {
+            if value == 0 {
+                errors.push(format!("{name} must be greater than zero"));
+            }
+        }
+    }
+
+    pub fn addr(&self) -> String {
+        format!("{}:{}", self.http.host, self.http.port)
+    }
+
+    pub fn migration_lock_timeout(&self) -> Duration {
+        Duration::from_secs(self.database.migration_lock_timeout_secs)
+    }
+
+    pub fn shutdown_timeout(&self) -> Duration {
+        Duration::from_secs(self.http.shutdown_timeout_secs)
+    }
+
+    pub fn ingest_config(&self) -> IngestConfig {
+        IngestConfig {
+            capacity: self.ingest.buffer_size,
+            batch_size: self.ingest.batch_size,
+            flush_interval: Duration::from_millis(self.ingest.flush_interval_ms),
+        }
+    }
+
+    pub fn retention_policy(&self) -> RetentionPolicy {
+        let age = |days: RetentionDays| days.0.map(|d| chrono::Duration::days(d.into()));
+
+        RetentionPolicy {
+            views: age(self.retention.view_days),
+            likes: age(self.retention.like_days),
+            shares: age(self.retention.share_days),
+            interval: Duration::from_secs(self.retention.interval_secs),
+            batch_size: self.retention.batch_size,
+        }
+    }
+
+    pub fn catalog_policy(&self) -> CatalogPolicy {
+        CatalogPolicy {
+            interval: Duration::from_secs(self.catalog.interval_secs),
+            max_age: chrono::Duration::seconds(self.catalog.max_age_secs as i64),
+            batch_size: self.catalog.batch_size,
+        }
+    }
+
+    pub fn recommendation_policy(&self) -> RecommendationPolicy {
+        RecommendationPolicy {
+            interval: Duration::from_secs(self.recommendations.interval_secs),
+            window: chrono::Duration::days(self.recommendations.window_days.into()),
+            max_posts_per_user: self.recommendations.max_posts_per_user,
+            related_per_post: self.recommendations.related_per_post,
+            batch_size: self.recommendations.batch_size,
+        }
+    }
+}
+
+struct Loader<'a> {
+    env: &'a dyn Fn(&str) -> Option<String>,
+    errors: Vec<String>,
+}
+
+impl Loader<'_> {
+    fn read_file(&mut self, path: PathBuf) -> Config {
+        let contents = match fs::read_to_string(&path) {
+            Ok(contents) => contents,
+            Err(e) => {
+                self.errors.push(format!("{}: {e}", path.display()));
+                return Config::default();
+            }
+        };
+
+        toml::from_str(&contents).unwrap_or_else(|e| {
+            self.errors.push(format!("{}: {e}", path.display()));
+            Config::default()
+        })
+    }
+
+    fn apply_env(&mut self, config: &mut Config) {
+        self.set("HOST", &mut config.http.host);
+        self.set("PORT", &mut config.http.port);
+        self.set(
+            "SHUTDOWN_TIMEOUT_SECS",
+            &mut config.http.shutdown_timeout_secs,
+        );
+
+        self.set_cors("CORS", &mut config.cors.public);
+        self.set_cors("PRIVATE_CORS", &mut config.cors.private);
+
+        self.set("DATABASE_URL", &mut config.database.url);
+        self.set(
+            "DATABASE_MAX_CONNECTIONS",
+            &mut config.database.max_connections,
+        );
+        self.set(
+            "DATABASE_ACQUIRE_TIMEOUT_SECS",
+            &mut config.database.acquire_timeout_secs,
+        );
+        self.set("DATABASE_AUTO_MIGRATE", &mut config.database.auto_migrate);
+        self.set(
+            "DATABASE_MIGRATION_LOCK_TIMEOUT_SECS",
+            &mut config.database.migration_lock_timeout_secs,
+        );
+
+        self.set_opt("USERS_URL", &mut config.users.url);
+        self.set("USERS_AUTH", &mut config.users.auth);
+        self.set("USERS_TIMEOUT_SECS", &mut config.users.timeout_secs);
+        self.set_opt("JWT_SECRET", &mut config.users.jwt_secret);
+        self.set_opt("JWT_JWKS_FILE", &mut config.users.jwt_jwks_file);
+        self.set_list("JWT_AUDIENCE", &mut config.users.jwt_audience);
+        self.set_opt("JWT_USER_ID_CLAIM", &mut config.users.jwt_user_id_claim);
+
+        self.set("ARTICLES_URL", &mut config.articles.url);
+        self.set("ARTICLES_TIMEOUT_SECS", &mut config.articles.timeout_secs);
+        self.set(
+            "ARTICLES_CACHE_TTL_SECS",
+            &mut config.articles.cache_ttl_secs,
+        );
+        self.set(
+            "ARTICLES_CACHE_CAPACITY",
+            &mut config.articles.cache_capacity,
+        );
+
+        self.set_opt("API_KEYS_FILE", &mut config.auth.api_keys_file);
+        self.set_list("INTERNAL_SECRET_TOKEN", &mut config.auth.internal_tokens);
+
+        self.set("INGEST_BUFFER_SIZE", &mut config.ingest.buffer_size);
+        self.set("INGEST_BATCH_SIZE", &mut config.ingest.batch_size);
+        self.set(
+            "INGEST_FLUSH_INTERVAL_MS",
+            &mut config.ingest.flush_interval_ms,
+        );
+
+        self.set("RETENTION_VIEW_DAYS", &mut config.retention.view_days);
+        self.set("RETENTION_LIKE_DAYS", &mut config.retention.like_days);
+        self.set("RETENTION_SHARE_DAYS", &mut config.retention.share_days);
+        self.set(
+            "RETENTION_INTERVAL_SECS",
+            &mut config.retention.interval_secs,
+        );
+        self.set("RETENTION_BATCH_SIZE", &mut config.retention.batch_size);
+
+        self.set("CATALOG_INTERVAL_SECS", &mut config.catalog.interval_secs);
+        self.set("CATALOG_MAX_AGE_SECS", &mut config.catalog.max_age_secs);
+        self.set("CATALOG_BATCH_SIZE", &mut config.catalog.batch_size);
+
+        self.set(
+            "RECOMMENDATIONS_INTERVAL_SECS",
+            &mut config.recommendations.interval_secs,
+        );
+        self.set(
+            "RECOMMENDATIONS_WINDOW_DAYS",
+            &mut config.recommendations.window_days,
+        );
+        self.set(
+            "RECOMMENDATIONS_MAX_POSTS_PER_USER",
+            &mut config.recommendations.max_posts_per_user,
+        );
+        self.set(
+            "RECOMMENDATIONS_RELATED_PER_POST",
+            &mut config.recommendations.related_per_post,
+        );
+        self.set(
+            "RECOMMENDATIONS_BATCH_SIZE",
+            &mut config.recommendations.batch_size,
+        );
+
+        self.set("ERASURE_MODE", &mut config.privacy.erasure_mode);
+        self.set("POST_PURGE_MODE", &mut config.privacy.post_purge_mode);
+
+        self.set("ENABLE_DOCS", &mut config.features.docs);
+        self.set("ENABLE_METRICS", &mut config.features.metrics);
+        self.set("ENABLE_RETENTION", &mut config.features.retention);
+        self.set("ENABLE_CATALOG", &mut config.features.catalog);
+        self.set(
+            "ENABLE_RECOMMENDATIONS",
+            &mut config.features.recommendations,
+        );
+        self.set(
+            "ENABLE_ARTICLES_WEBHOOK",
+            &mut config.features.articles_webhook,
+        );
+    }
+
+    fn set<T>(&mut self, name: &str, target: &mut T)
+    where
+        T: FromStr,
+        T::Err: fmt::Display,
+    {
+        if let Some(value) = (self.env)(name) {
+            match value.trim().parse() {
+                Ok(value) => *target = value,
+                Err(e) => self.errors.push(format!("{name}: {e}")),
+            }
+        }
+    }
+
+    fn set_opt<T>(&mut self, name: &str, target: &mut Option<T>)
+    where
+        T: FromStr,
+        T::Err: fmt::Display,
+    {
+        if let Some(value) = (self.env)(name) {
+            match value.trim().parse() {
+                Ok(value) => *target = Some(value),
+                Err(e) => self.errors.push(format!("{name}: {e}")),
+            }
+        }
+    }
+
+    fn set_cors(&mut self, prefix: &str, policy: &mut CorsPolicy) {
+        self.set(&format!("{prefix}_ENABLED"), &mut policy.enabled);
+        self.set_list(&format!("{prefix}_ALLOWED_ORIGINS"), &mut policy.origins);
+        self.set_list(&format!("{prefix}_ALLOWED_METHODS"), &mut policy.methods);
+        self.set_list(&format!("{prefix}_ALLOWED_HEADERS"), &mut policy.headers);
+        self.set(
+            &format!("{prefix}_ALLOW_CREDENTIALS"),
+            &mut policy.credentials,
+        );
+    }
+
+    /// Reads a comma separated list, ignoring empty items.
+    fn set_list(&mut self, name: &str, target: &mut Vec<String>) {
+        if let Some(value) = (self.env)(name) {
+            *target = value
+                .split(',')
+                .map(str::trim)
+                .filter(|item| !item.is_empty())
+                .map(str::to_owned)
+                .collect();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::*;
+
+    fn load(vars: &[(&str, &str)]) -> Result<Config, ConfigError> {
+        let vars: HashMap<String, String> = vars
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect();
+
+        Config::load_from(&ConfigArgs::default(), Usage::Serve, |name| {
+            vars.get(name).cloned()
+        })
+    }
+
+    const REQUIRED: [(&str, &str); 4] = [
+        ("DATABASE_URL", "mysql://root@localhost/db"),
+        ("USERS_URL", "http://users"),
+        ("ARTICLES_URL", "http://articles"),
+        ("INTERNAL_SECRET_TOKEN", "secret"),
+    ];
+
+    #[test]
+    fn reads_environment() {
+        let mut vars = REQUIRED.to_vec();
+        vars.extend([
+            ("PORT", "9000"),
+            ("INTERNAL_SECRET_TOKEN", "new, old"),
+            ("RETENTION_VIEW_DAYS", "forever"),
+            ("ENABLE_DOCS", "false"),
+        ]);
+
+        let config = load(&vars).unwrap();
+
+        assert_eq!(config.addr(), "0.0.0.0:9000");
+        assert_eq!(config.auth.internal_tokens, ["new", "old"]);
+        assert!(config.retention_policy().keeps_everything());
+        assert!(!config.features.docs);
+    }
+
+    #[test]
+    fn reports_every_error() {
+        let err = load(&[("PORT", "http"), ("INGEST_BATCH_SIZE", "0")]).unwrap_err();
+
+        let errors = err.0.join("\n");
+        assert_eq!(err.0.len(), 6, "{errors}");
+        assert!(errors.contains("PORT"));
+        assert!(errors.contains("database.url"));
+        assert!(errors.contains("users.url"));
+        assert!(errors.contains("articles.url"));
+        assert!(errors.contains("No API keys configured"));
+        assert!(errors.contains("ingest.batch_size"));
+    }
+
+    #[test]
+    fn bounds_retention() {
+        let mut vars = REQUIRED.to_vec();
+        vars.extend([
+            ("RETENTION_VIEW_DAYS", "100000000"),
+            ("RETENTION_BATCH_SIZE", "0"),
+            ("RETENTION_INTERVAL_SECS", "0"),
+            ("CATALOG_MAX_AGE_SECS", "0"),
+        ]);
+
+        let errors = load(&vars).unwrap_err().0.join("\n");
+        assert!(errors.contains("retention.view_days"));
+        assert!(errors.contains("retention.batch_size"));
+        assert!(errors.contains("retention.interval_secs"));
+        assert!(errors.contains("catalog.max_age_secs"));
+    }
+
+    #[test]
+    fn maintenance_only_validates_the_database() {
+        let vars: HashMap<String, String> = [("DATABASE_URL", "mysql://root@localhost/db")]
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect();
+        let load = |usage| {
+            Config::load_from(&ConfigArgs::default(), usage, |name| {
+                vars.get(name).cloned()
+            })
+        };
+
+        assert!(load(Usage::Database).is_ok());
+        assert!(load(Usage::Serve).is_err());
+        assert!(Config::load_from(&ConfigArgs::default(), Usage::Database, |_| None).is_err());
+    }
+
+    #[test]
+    fn private_cors_is_off_by_default() {
+        let config = load(&REQUIRED).unwrap();
+        assert!(config.cors.public.enabled);
+        assert!(!config.cors.private.enabled);
+    }
+
+    #[test]
+    fn cors_credentials_need_explicit_lists() {
+        let mut vars = REQUIRED.to_vec();
+        vars.extend([
+            ("PRIVATE_CORS_ENABLED", "true"),
+            ("PRIVATE_CORS_ALLOW_CREDENTIALS", "true"),
+            ("PRIVATE_CORS_ALLOWED_ORIGINS", "https://admin.mittel.dev"),
+        ]);
+        let err = load(&vars).unwrap_err();
+        assert_eq!(err.0.len(), 1);
+        assert!(err.0[0].contains("cors.private.credentials"));
+
+        vars.extend([
+            ("PRIVATE_CORS_ALLOWED_METHODS", "GET, DELETE"),
+            ("PRIVATE_CORS_ALLOWED_HEADERS", "x-internal-token"),
+        ]);
+        assert!(load(&vars).is_ok());
+    }
+
+    #[test]
+    fn jwt_auth_needs_a_key_instead_of_a_url() {
+        let vars = [
+            ("DATABASE_URL", "mysql://root@localhost/db"),
+            ("ARTICLES_URL", "http://articles"),
+            ("INTERNAL_SECRET_TOKEN", "secret"),
+            ("USERS_AUTH", "jwt"),
+        ];
+        let err = load(&vars).unwrap_err();
+        assert_eq!(err.0.len(), 1);
+        assert!(err.0[0].contains("jwt_secret"));
+
+        let mut vars = vars.to_vec();
+        vars.push(("JWT_SECRET", "secret"));
+        assert!(load(&vars).is_ok());
+
+        vars.push(("JWT_JWKS_FILE", "/nonexistent/jwks.json"));
+        let err = load(&vars).unwrap_err();
+        assert_eq!(err.0.len(), 1);
+        assert!(err.0[0].contains("users.jwt_jwks_file"));
+    }
+
+    #[test]
+    fn validates_api_keys() {
+        let mut vars = REQUIRED.to_vec();
+        vars.push(("INTERNAL_SECRET_TOKEN", "a, a"));
+        let err = load(&vars).unwrap_err();
+        assert_eq!(err.0.len(), 1);
+        assert!(err.0[0].contains("reuses the token"));
+
+        vars.push(("API_KEYS_FILE", "/nonexistent/api-keys.json"));
+        let err = load(&vars).unwrap_err();
+        assert!(err.0[0].contains("api-keys.json"));
+    }
+
+    #[test]
+    fn parses_toml() {
+        let config: Config = toml::from_str(
+            r#"
+            [http]
+            port = 3000
+
+            [cors.private]
+            enabled = true
+            origins = ["https://admin.mittel.dev"]
+
+            [retention]
+            view_days = 30
+            like_days = "forever"
+
+            [privacy]
+            erasure_mode = "delete"
+            "#,
+        )
+        .unwrap();
+
+        assert_eq!(config.http.port, 3000);
+        assert!(config.cors.public.enabled);
+        assert_eq!(config.cors.private.origins, ["https://admin.mittel.dev"]);
+        assert_eq!(config.retention.view_days, RetentionDays(Some(30)));
+        assert_eq!(config.retention.like_days, RetentionDays(None));
+        assert_eq!(config.privacy.erasure_mode, ErasureMode::Delete);
+    }
+
+    #[test]
+    fn rejects_unknown_toml_keys() {
+        assert!(toml::from_str::<Config>("[http]\nprot = 3000").is_err());
+    }
+}
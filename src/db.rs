@@ -1,13 +1,31 @@
+use std::{
+    collections::{BTreeMap, BTreeSet, HashMap},
+    time::Duration,
+};
+
 use anyhow::anyhow;
 use async_trait::async_trait;
+use chrono::{DateTime, NaiveDate, NaiveTime, Utc};
+use futures_util::{TryStreamExt, stream::BoxStream};
 use num_traits::cast::ToPrimitive;
-use serde::Deserialize;
-use sqlx::{MySqlPool, mysql::MySqlPoolOptions};
+use serde::{Deserialize, Serialize};
+use sqlx::{
+    MySql as MySqlDb, MySqlConnection, MySqlPool, QueryBuilder, migrate::Migrator,
+    mysql::MySqlPoolOptions, pool::PoolConnection,
+};
 use utoipa::ToSchema;
 
-use crate::domain::{Device, Event, EventKind, EventSummary};
+use crate::{
+    config::DatabaseConfig,
+    domain::{
+        AuthorEngagement, Breakdown, DailyEngagement, Device, ErasureMode, ErasureReport, Event,
+        EventKind, EventSummary, HistoryEntry, PostAnalytics, PurgeMode, PurgeReport,
+        Recommendation, TagEngagement,
+    },
+    posts::Post,
+};
 
-#[derive(Debug, Clone, Deserialize, ToSchema)]
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Deserialize, ToSchema)]
 pub struct DeviceRequest {
     pub os: String,
     pub browser: String,
@@ -20,10 +38,74 @@ pub struct CreateEventRequest {
     pub post_id: String,
     pub kind: EventKind,
     pub device: Option<DeviceRequest>,
+    /// URL of the page the reader came from. Only its host is kept.
+    pub referrer: Option<String>,
+}
+
+/// An event accepted by the API, waiting to be written.
+#[derive(Debug, Clone)]
+pub struct NewEvent {
+    pub post_id: String,
+    pub kind: EventKind,
+    pub device: Option<DeviceRequest>,
+    pub user_id: Option<String>,
+    pub referrer: Option<String>,
+    pub timestamp: DateTime<Utc>,
+}
+
+/// A post a signed in user engaged with, one per kind.
+#[derive(Debug, Clone)]
+pub struct Interaction {
+    pub user_id: String,
+    pub post_id: String,
+    pub kind: EventKind,
+}
+
+/// Whether a migration known to this build has been applied to the database.
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct MigrationInfo {
+    pub version: i64,
+    pub description: String,
+    pub applied: bool,
+}
+
+/// Applied and pending migrations known to this build.
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct MigrationReport {
+    pub applied: usize,
+    pub pending: usize,
+    pub migrations: Vec<MigrationInfo>,
+}
+
+impl From<Vec<MigrationInfo>> for MigrationReport {
+    fn from(migrations: Vec<MigrationInfo>) -> Self {
+        let applied = migrations.iter().filter(|m| m.applied).count();
+
+        Self {
+            applied,
+            pending: migrations.len() - applied,
+            migrations,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct PoolStats {
+    pub max: u32,
+    pub size: u32,
+    pub idle: usize,
 }
 
 #[async_trait]
 pub trait EventRepository: Send + Sync {
+    /// Checks that the database can be reached.
+    async fn ping(&self) -> anyhow::Result<()>;
+
+    fn pool_stats(&self) -> PoolStats;
+
+    /// Reads which migrations are applied, without modifying the database.
+    async fn migration_status(&self) -> anyhow::Result<Vec<MigrationInfo>>;
+
     async fn find_events(
         &self,
         user_id: &Option<String>,
@@ -32,13 +114,175 @@ pub trait EventRepository: Send + Sync {
 
     async fn find_event_summary(&self, post_id: &str) -> anyhow::Result<EventSummary>;
 
-    async fn create_event(
+    /// Returns the summary of each of the posts, in the same order.
+    async fn find_event_summaries(&self, post_ids: &[&str]) -> anyhow::Result<Vec<EventSummary>>;
+
+    /// Gathers the analytics of a post for the days in `from..to`.
+    async fn find_post_analytics(
         &self,
-        event: CreateEventRequest,
-        user_id: Option<String>,
-    ) -> anyhow::Result<i64>;
+        post_id: &str,
+        from: NaiveDate,
+        to: NaiveDate,
+    ) -> anyhow::Result<PostAnalytics>;
+
+    /// Writes a batch of events and updates their posts' counters in a single
+    /// transaction.
+    async fn create_events(&self, events: &[NewEvent]) -> anyhow::Result<()>;
 
     async fn find_devices(&self) -> anyhow::Result<Vec<Device>>;
+
+    /// Streams every event recorded for the user, archived ones included, oldest
+    /// first.
+    fn stream_user_events(&self, user_id: String) -> BoxStream<'static, anyhow::Result<Event>>;
+
+    /// Streams every event recorded for the post, archived ones included, oldest
+    /// first.
+    fn stream_post_events(&self, post_id: String) -> BoxStream<'static, anyhow::Result<Event>>;
+
+    async fn find_user_devices(&self, user_id: &str) -> anyhow::Result<Vec<Device>>;
+
+    async fn find_user_event_summary(&self, user_id: &str) -> anyhow::Result<EventSummary>;
+
+    /// Returns up to `limit` of the user's history entries, optionally of one
+    /// kind, newest first, starting after the entry with ID `before`.
+    async fn find_user_history(
+        &self,
+        user_id: &str,
+        kind: Option<EventKind>,
+        before: Option<i64>,
+        limit: u32,
+    ) -> anyhow::Result<Vec<HistoryEntry>>;
+
+    async fn erase_user_events(
+        &self,
+        user_id: &str,
+        mode: ErasureMode,
+    ) -> anyhow::Result<ErasureReport>;
+
+    async fn purge_post_events(
+        &self,
+        post_id: &str,
+        mode: PurgeMode,
+    ) -> anyhow::Result<PurgeReport>;
+
+    /// Rolls up to `limit` events of `kind` older than `before` into daily
+    /// aggregates and deletes them, returning how many were deleted.
+    async fn roll_up_events(
+        &self,
+        kind: EventKind,
+        before: DateTime<Utc>,
+        limit: u32,
+    ) -> anyhow::Result<u64>;
+
+    /// Recomputes the counters of one post, or of every post, from the raw
+    /// events and daily aggregates, returning how many posts were counted.
+    async fn rebuild_counters(&self, post_id: Option<&str>) -> anyhow::Result<u64>;
+
+    /// Returns up to `limit` posts with events whose metadata is missing or was
+    /// refreshed before `refreshed_before`, least recently refreshed first.
+    async fn find_stale_post_metadata(
+        &self,
+        refreshed_before: DateTime<Utc>,
+        limit: u32,
+    ) -> anyhow::Result<Vec<String>>;
+
+    /// Returns up to `limit` posts of the author, as last fetched from the
+    /// articles service, newest first.
+    async fn find_author_post_ids(
+        &self,
+        author_id: &str,
+        limit: u32,
+    ) -> anyhow::Result<Vec<String>>;
+
+    /// Stores the metadata of a post, or `None` if the articles service no
+    /// longer knows it.
+    async fn save_post_metadata(&self, post_id: &str, post: Option<&Post>) -> anyhow::Result<()>;
+
+    /// Sums the events of the days in `from..to` by tag, returning the `limit`
+    /// tags with the most events.
+    async fn find_tag_engagement(
+        &self,
+        from: NaiveDate,
+        to: NaiveDate,
+        limit: usize,
+    ) -> anyhow::Result<Vec<TagEngagement>>;
+
+    /// Sums the events of the days in `from..to` by author, returning the
+    /// `limit` authors with the most events.
+    async fn find_author_engagement(
+        &self,
+        from: NaiveDate,
+        to: NaiveDate,
+        limit: usize,
+    ) -> anyhow::Result<Vec<AuthorEngagement>>;
+
+    /// Returns up to `limit` of what signed in users engaged with since
+    /// `since`, ordered by user, post and kind, starting after `after`.
+    async fn find_interactions(
+        &self,
+        since: DateTime<Utc>,
+        after: Option<&Interaction>,
+        limit: u32,
+    ) -> anyhow::Result<Vec<Interaction>>;
+
+    /// Replaces every stored related post in a single transaction.
+    async fn replace_related_posts(
+        &self,
+        related: &BTreeMap<String, Vec<Recommendation>>,
+    ) -> anyhow::Result<()>;
+
+    /// Returns up to `limit` posts related to a post, best first.
+    async fn find_related_posts(
+        &self,
+        post_id: &str,
+        limit: u32,
+    ) -> anyhow::Result<Vec<Recommendation>>;
+
+    /// Returns up to `limit` posts related to those the user engaged with,
+    /// best first, leaving out the ones they already engaged with.
+    async fn find_recommendations(
+        &self,
+        user_id: &str,
+        limit: u32,
+    ) -> anyhow::Result<Vec<Recommendation>>;
+}
+
+/// Sums per kind totals by key, returning the `limit` keys with the most events.
+pub(crate) fn rank_engagement(
+    rows: impl IntoIterator<Item = (String, EventKind, i64)>,
+    limit: usize,
+) -> Vec<(String, EventSummary)> {
+    let mut summaries: HashMap<String, EventSummary> = HashMap::new();
+    for (key, kind, total) in rows {
+        let summary = summaries.entry(key).or_default();
+        let total: usize = total.try_into().unwrap_or(0);
+        match kind {
+            EventKind::View => summary.views += total,
+            EventKind::Like => summary.likes += total,
+            EventKind::Share => summary.shares += total,
+        }
+    }
+
+    let events = |s: &EventSummary| s.views + s.likes + s.shares;
+    let mut ranked: Vec<_> = summaries.into_iter().collect();
+    ranked.sort_unstable_by(|(a_key, a), (b_key, b)| {
+        events(b).cmp(&events(a)).then_with(|| a_key.cmp(b_key))
+    });
+    ranked.truncate(limit);
+    ranked
+}
+
+static MIGRATOR: Migrator = sqlx::migrate!();
+
+/// MySQL user lock held while migrations run.
+const MIGRATION_LOCK: &str = "mittel_engagement_migrations";
+
+async fn unlock_migrations(conn: &mut MySqlConnection) -> anyhow::Result<()> {
+    sqlx::query_scalar!("select release_lock(?) as released", MIGRATION_LOCK)
+        .fetch_one(conn)
+        .await
+        .map_err(|e| anyhow!(e))?;
+    Ok(())
 }
 
 #[derive(Clone)]
@@ -47,20 +291,149 @@ pub struct MySql {
 }
 
 impl MySql {
-    pub async fn new(database_url: &str) -> sqlx::Result<Self> {
+    /// Connects to the database without touching its schema.
+    pub async fn connect(config: &DatabaseConfig) -> sqlx::Result<Self> {
         let pool = MySqlPoolOptions::new()
-            .max_connections(5)
-            .connect(database_url)
+            .max_connections(config.max_connections)
+            .acquire_timeout(Duration::from_secs(config.acquire_timeout_secs))
+            .connect(&config.url)
             .await?;
 
-        sqlx::migrate!().run(&pool).await?;
-
         Ok(Self { pool })
     }
+
+    /// Applies every pending migration.
+    ///
+    /// Only one instance migrates at a time: the others wait up to
+    /// `lock_timeout` for it to finish, and then find nothing left to apply.
+    pub async fn migrate(&self, lock_timeout: Duration) -> anyhow::Result<()> {
+        let mut conn = self.lock_migrations(lock_timeout).await?;
+        let result = MIGRATOR.run(&mut *conn).await;
+        unlock_migrations(&mut conn).await?;
+
+        result.map_err(|e| anyhow!(e))
+    }
+
+    /// Reverts the last `steps` applied migrations, returning their versions.
+    pub async fn undo_migrations(
+        &self,
+        steps: usize,
+        lock_timeout: Duration,
+    ) -> anyhow::Result<Vec<i64>> {
+        let mut conn = self.lock_migrations(lock_timeout).await?;
+
+        let result = async {
+            let mut applied: Vec<i64> = self
+                .migration_status()
+                .await?
+                .into_iter()
+                .filter(|m| m.applied)
+                .map(|m| m.version)
+                .collect();
+            applied.sort_unstable_by(|a, b| b.cmp(a));
+
+            let target = applied.get(steps).copied().unwrap_or(0);
+            MIGRATOR
+                .undo(&mut *conn, target)
+                .await
+                .map_err(|e| anyhow!(e))?;
+
+            Ok(applied.into_iter().take(steps).collect())
+        }
+        .await;
+
+        unlock_migrations(&mut conn).await?;
+        result
+    }
+
+    /// Takes the migration lock on a dedicated connection. The lock belongs to
+    /// that connection, so it must be released on it too.
+    async fn lock_migrations(&self, timeout: Duration) -> anyhow::Result<PoolConnection<MySqlDb>> {
+        let mut conn = self.pool.acquire().await.map_err(|e| anyhow!(e))?;
+
+        let locked = sqlx::query_scalar!(
+            "select get_lock(?, ?) as locked",
+            MIGRATION_LOCK,
+            timeout.as_secs()
+        )
+        .fetch_one(&mut *conn)
+        .await
+        .map_err(|e| anyhow!(e))?;
+
+        match locked {
+            Some(1) => Ok(conn),
+            _ => Err(anyhow!(
+                "Another instance is still migrating after {timeout:?}"
+            )),
+        }
+    }
+
+    /// Waits for checked out connections to be returned and closes the pool.
+    pub async fn close(&self) {
+        self.pool.close().await;
+    }
+}
+
+/// Lists the migrations of this build, given the versions already applied.
+fn migration_infos(applied: &[i64]) -> Vec<MigrationInfo> {
+    MIGRATOR
+        .iter()
+        .filter(|m| m.migration_type.is_up_migration())
+        .map(|m| MigrationInfo {
+            version: m.version,
+            description: m.description.to_string(),
+            applied: applied.contains(&m.version),
+        })
+        .collect()
 }
 
 #[async_trait]
 impl EventRepository for MySql {
+    #[tracing::instrument(skip_all)]
+    async fn ping(&self) -> anyhow::Result<()> {
+        sqlx::query!("select 1 as one")
+            .fetch_one(&self.pool)
+            .await
+            .map_err(|e| anyhow!(e))?;
+        Ok(())
+    }
+
+    fn pool_stats(&self) -> PoolStats {
+        PoolStats {
+            max: self.pool.options().get_max_connections(),
+            size: self.pool.size(),
+            idle: self.pool.num_idle(),
+        }
+    }
+
+    #[tracing::instrument(skip_all)]
+    async fn migration_status(&self) -> anyhow::Result<Vec<MigrationInfo>> {
+        // Only reads, as this also backs the readiness probe and runs on
+        // startup without `auto_migrate`. The table is created by the first
+        // migration run, so until then every migration is pending.
+        let tracked: i64 = sqlx::query_scalar(
+            r#"
+            select count(*) from information_schema.tables
+            where table_schema = database() and table_name = '_sqlx_migrations'
+            "#,
+        )
+        .fetch_one(&self.pool)
+        .await
+        .map_err(|e| anyhow!(e))?;
+
+        let applied: Vec<i64> = if tracked > 0 {
+            sqlx::query_scalar("select version from _sqlx_migrations where success")
+                .fetch_all(&self.pool)
+                .await
+                .map_err(|e| anyhow!(e))?
+        } else {
+            Vec::new()
+        };
+
+        Ok(migration_infos(&applied))
+    }
+
+    #[tracing::instrument(skip_all)]
     async fn find_events(
         &self,
         user_id: &Option<String>,
@@ -75,6 +448,7 @@ impl EventRepository for MySql {
                 device_id,
                 post_id,
                 kind as "kind: EventKind",
+                referrer,
                 timestamp
             from events
             where (? is null or user_id = ?)
@@ -90,36 +464,212 @@ impl EventRepository for MySql {
         .map_err(|e| anyhow!(e))?)
     }
 
+    #[tracing::instrument(skip_all)]
     async fn find_event_summary(&self, post_id: &str) -> anyhow::Result<EventSummary> {
         let rec = sqlx::query!(
+            "select views, likes, shares from post_counters where post_id = ?",
+            post_id
+        )
+        .fetch_optional(&self.pool)
+        .await
+        .map_err(|e| anyhow!(e))?;
+
+        Ok(rec
+            .map(|rec| EventSummary {
+                views: rec.views.try_into().unwrap_or(0),
+                likes: rec.likes.try_into().unwrap_or(0),
+                shares: rec.shares.try_into().unwrap_or(0),
+            })
+            .unwrap_or_default())
+    }
+
+    #[tracing::instrument(skip_all)]
+    async fn find_event_summaries(&self, post_ids: &[&str]) -> anyhow::Result<Vec<EventSummary>> {
+        let mut summaries: HashMap<String, EventSummary> = HashMap::new();
+
+        for chunk in post_ids.chunks(1000) {
+            let mut query = QueryBuilder::<sqlx::MySql>::new(
+                "select post_id, views, likes, shares from post_counters where post_id in (",
+            );
+            let mut ids = query.separated(", ");
+            for post_id in chunk {
+                ids.push_bind(*post_id);
+            }
+            query.push(")");
+
+            let rows: Vec<(String, i64, i64, i64)> = query
+                .build_query_as()
+                .fetch_all(&self.pool)
+                .await
+                .map_err(|e| anyhow!(e))?;
+
+            for (post_id, views, likes, shares) in rows {
+                let summary = EventSummary {
+                    views: views.try_into().unwrap_or(0),
+                    likes: likes.try_into().unwrap_or(0),
+                    shares: shares.try_into().unwrap_or(0),
+                };
+                summaries.insert(post_id, summary);
+            }
+        }
+
+        Ok(post_ids
+            .iter()
+            .map(|post_id| summaries.remove(*post_id).unwrap_or_default())
+            .collect())
+    }
+
+    #[tracing::instrument(skip_all)]
+    async fn find_post_analytics(
+        &self,
+        post_id: &str,
+        from: NaiveDate,
+        to: NaiveDate,
+    ) -> anyhow::Result<PostAnalytics> {
+        let summary = self.find_event_summary(post_id).await?;
+
+        let start = from.and_time(NaiveTime::MIN).and_utc();
+        let end = to.and_time(NaiveTime::MIN).and_utc();
+
+        let mut daily: BTreeMap<NaiveDate, DailyEngagement> = from
+            .iter_days()
+            .take_while(|day| *day < to)
+            .map(|day| {
+                let engagement = DailyEngagement {
+                    day,
+                    ..Default::default()
+                };
+                (day, engagement)
+            })
+            .collect();
+
+        let mut add = |day: NaiveDate, kind: EventKind, total: i64| {
+            if let Some(engagement) = daily.get_mut(&day) {
+                let total = total.try_into().unwrap_or(0);
+                match kind {
+                    EventKind::View => engagement.views += total,
+                    EventKind::Like => engagement.likes += total,
+                    EventKind::Share => engagement.shares += total,
+                }
+            }
+        };
+
+        let recent = sqlx::query!(
             r#"
-            select
-                coalesce(sum(kind = 'view'), 0) as views,
-                coalesce(sum(kind = 'like'), 0) as likes,
-                coalesce(sum(kind = 'share'), 0) as shares
+            select date(timestamp) as "day!", kind as "kind: EventKind", count(*) as total
             from events
-            where ? is null or post_id = ?
+            where post_id = ? and timestamp >= ? and timestamp < ?
+            group by date(timestamp), kind
             "#,
             post_id,
-            post_id
+            start,
+            end
         )
-        .fetch_one(&self.pool)
+        .fetch_all(&self.pool)
+        .await
+        .map_err(|e| anyhow!(e))?;
+
+        for rec in recent {
+            add(rec.day, rec.kind, rec.total);
+        }
+
+        let rolled_up = sqlx::query!(
+            r#"
+            select day, kind as "kind: EventKind", total
+            from event_daily_aggregates
+            where post_id = ? and day >= ? and day < ?
+            "#,
+            post_id,
+            from,
+            to
+        )
+        .fetch_all(&self.pool)
+        .await
+        .map_err(|e| anyhow!(e))?;
+
+        for rec in rolled_up {
+            add(rec.day, rec.kind, rec.total);
+        }
+
+        let operating_systems = sqlx::query_as!(
+            Breakdown,
+            r#"
+            select d.os as value, cast(count(*) as unsigned) as "events!"
+            from events e
+            join devices d on d.id = e.device_id
+            where e.post_id = ? and e.timestamp >= ? and e.timestamp < ?
+            group by d.os
+            order by count(*) desc
+            limit 20
+            "#,
+            post_id,
+            start,
+            end
+        )
+        .fetch_all(&self.pool)
+        .await
+        .map_err(|e| anyhow!(e))?;
+
+        let browsers = sqlx::query_as!(
+            Breakdown,
+            r#"
+            select d.browser as value, cast(count(*) as unsigned) as "events!"
+            from events e
+            join devices d on d.id = e.device_id
+            where e.post_id = ? and e.timestamp >= ? and e.timestamp < ?
+            group by d.browser
+            order by count(*) desc
+            limit 20
+            "#,
+            post_id,
+            start,
+            end
+        )
+        .fetch_all(&self.pool)
+        .await
+        .map_err(|e| anyhow!(e))?;
+
+        let referrers = sqlx::query_as!(
+            Breakdown,
+            r#"
+            select referrer as "value!", cast(count(*) as unsigned) as "events!"
+            from events
+            where post_id = ? and referrer is not null and timestamp >= ? and timestamp < ?
+            group by referrer
+            order by count(*) desc
+            limit 20
+            "#,
+            post_id,
+            start,
+            end
+        )
+        .fetch_all(&self.pool)
         .await
         .map_err(|e| anyhow!(e))?;
 
-        Ok(EventSummary {
-            views: rec.views.to_usize().unwrap(),
-            likes: rec.likes.to_usize().unwrap(),
-            shares: rec.shares.to_usize().unwrap(),
+        Ok(PostAnalytics {
+            post_id: post_id.to_owned(),
+            summary,
+            daily: daily.into_values().collect(),
+            operating_systems,
+            browsers,
+            referrers,
         })
     }
 
-    async fn create_event(
-        &self,
-        event: CreateEventRequest,
-        user_id: Option<String>,
-    ) -> anyhow::Result<i64> {
-        let device_id: Option<i64> = if let Some(device) = event.device {
+    #[tracing::instrument(skip_all)]
+    async fn create_events(&self, events: &[NewEvent]) -> anyhow::Result<()> {
+        if events.is_empty() {
+            return Ok(());
+        }
+
+        let mut device_ids: HashMap<&DeviceRequest, i64> = HashMap::new();
+
+        for device in events.iter().filter_map(|e| e.device.as_ref()) {
+            if device_ids.contains_key(device) {
+                continue;
+            }
+
             let rec = sqlx::query_as!(
                 Event,
                 r#"
@@ -136,33 +686,849 @@ impl EventRepository for MySql {
             .await
             .map_err(|e| anyhow!(e))?;
 
-            Some(rec.last_insert_id().try_into().unwrap())
+            device_ids.insert(device, rec.last_insert_id().try_into().unwrap());
+        }
+
+        let mut counters: HashMap<&str, [i64; 3]> = HashMap::new();
+        for event in events {
+            let counter = counters.entry(&event.post_id).or_default();
+            match event.kind {
+                EventKind::View => counter[0] += 1,
+                EventKind::Like => counter[1] += 1,
+                EventKind::Share => counter[2] += 1,
+            }
+        }
+
+        let mut tx = self.pool.begin().await.map_err(|e| anyhow!(e))?;
+
+        // Keeps each insert well below MySQL's limit of 65535 placeholders.
+        for chunk in events.chunks(1000) {
+            QueryBuilder::<sqlx::MySql>::new(
+                "insert into events (user_id, device_id, post_id, kind, referrer, timestamp) ",
+            )
+            .push_values(chunk, |mut row, event| {
+                row.push_bind(&event.user_id)
+                    .push_bind(event.device.as_ref().map(|d| device_ids[d]))
+                    .push_bind(&event.post_id)
+                    .push_bind(event.kind)
+                    .push_bind(&event.referrer)
+                    .push_bind(event.timestamp);
+            })
+            .build()
+            .execute(&mut *tx)
+            .await
+            .map_err(|e| anyhow!(e))?;
+        }
+
+        for (post_id, [views, likes, shares]) in counters {
+            sqlx::query!(
+                r#"
+                insert into post_counters (post_id, views, likes, shares)
+                values (?, ?, ?, ?)
+                on duplicate key update
+                    views = views + values(views),
+                    likes = likes + values(likes),
+                    shares = shares + values(shares)
+                "#,
+                post_id,
+                views,
+                likes,
+                shares,
+            )
+            .execute(&mut *tx)
+            .await
+            .map_err(|e| anyhow!(e))?;
+        }
+
+        tx.commit().await.map_err(|e| anyhow!(e))?;
+
+        Ok(())
+    }
+
+    #[tracing::instrument(skip_all)]
+    async fn find_devices(&self) -> anyhow::Result<Vec<Device>> {
+        Ok(sqlx::query_as!(Device, "select * from devices")
+            .fetch_all(&self.pool)
+            .await
+            .map_err(|e| anyhow!(e))?)
+    }
+
+    fn stream_user_events(&self, user_id: String) -> BoxStream<'static, anyhow::Result<Event>> {
+        let pool = self.pool.clone();
+
+        Box::pin(async_stream::try_stream! {
+            let mut events = sqlx::query_as!(
+                Event,
+                r#"
+                select
+                    id,
+                    user_id,
+                    device_id,
+                    post_id,
+                    kind as "kind: EventKind",
+                    referrer,
+                    timestamp
+                from events
+                where user_id = ?
+                union all
+                select id, user_id, device_id, post_id, kind, referrer, timestamp
+                from events_archive
+                where user_id = ?
+                order by timestamp, id
+                "#,
+                user_id,
+                user_id
+            )
+            .fetch(&pool);
+
+            while let Some(event) = events.try_next().await? {
+                yield event;
+            }
+        })
+    }
+
+    fn stream_post_events(&self, post_id: String) -> BoxStream<'static, anyhow::Result<Event>> {
+        let pool = self.pool.clone();
+
+        Box::pin(async_stream::try_stream! {
+            let mut events = sqlx::query_as!(
+                Event,
+                r#"
+                select
+                    id,
+                    user_id,
+                    device_id,
+                    post_id,
+                    kind as "kind: EventKind",
+                    referrer,
+                    timestamp
+                from events
+                where post_id = ?
+                union all
+                select id, user_id, device_id, post_id, kind, referrer, timestamp
+                from events_archive
+                where post_id = ?
+                order by timestamp, id
+                "#,
+                post_id,
+                post_id
+            )
+            .fetch(&pool);
+
+            while let Some(event) = events.try_next().await? {
+                yield event;
+            }
+        })
+    }
+
+    #[tracing::instrument(skip_all)]
+    async fn find_user_devices(&self, user_id: &str) -> anyhow::Result<Vec<Device>> {
+        Ok(sqlx::query_as!(
+            Device,
+            r#"
+            select id, os, browser, screen_resolution, language
+            from devices
+            where id in (
+                select device_id from events where user_id = ?
+                union
+                select device_id from events_archive where user_id = ?
+            )
+            "#,
+            user_id,
+            user_id
+        )
+        .fetch_all(&self.pool)
+        .await
+        .map_err(|e| anyhow!(e))?)
+    }
+
+    #[tracing::instrument(skip_all)]
+    async fn find_user_event_summary(&self, user_id: &str) -> anyhow::Result<EventSummary> {
+        // One row for live events and one for archived ones.
+        let recs = sqlx::query!(
+            r#"
+            select
+                coalesce(sum(kind = 'view'), 0) as views,
+                coalesce(sum(kind = 'like'), 0) as likes,
+                coalesce(sum(kind = 'share'), 0) as shares
+            from events
+            where user_id = ?
+            union all
+            select
+                coalesce(sum(kind = 'view'), 0) as views,
+                coalesce(sum(kind = 'like'), 0) as likes,
+                coalesce(sum(kind = 'share'), 0) as shares
+            from events_archive
+            where user_id = ?
+            "#,
+            user_id,
+            user_id
+        )
+        .fetch_all(&self.pool)
+        .await
+        .map_err(|e| anyhow!(e))?;
+
+        let mut summary = EventSummary::default();
+        for rec in recs {
+            summary.views += rec.views.to_usize().unwrap();
+            summary.likes += rec.likes.to_usize().unwrap();
+            summary.shares += rec.shares.to_usize().unwrap();
+        }
+        Ok(summary)
+    }
+
+    #[tracing::instrument(skip_all)]
+    async fn find_user_history(
+        &self,
+        user_id: &str,
+        kind: Option<EventKind>,
+        before: Option<i64>,
+        limit: u32,
+    ) -> anyhow::Result<Vec<HistoryEntry>> {
+        // Event IDs grow with time, so the latest ID of each entry orders them
+        // and doubles as a stable cursor.
+        let recs = sqlx::query!(
+            r#"
+            select
+                max(id) as "id!",
+                post_id,
+                kind as "kind: EventKind",
+                max(timestamp) as "timestamp!",
+                count(*) as events
+            from events
+            where user_id = ? and (? is null or kind = ?)
+            group by post_id, kind
+            having ? is null or max(id) < ?
+            order by max(id) desc
+            limit ?
+            "#,
+            user_id,
+            kind,
+            kind,
+            before,
+            before,
+            limit
+        )
+        .fetch_all(&self.pool)
+        .await
+        .map_err(|e| anyhow!(e))?;
+
+        Ok(recs
+            .into_iter()
+            .map(|rec| HistoryEntry {
+                id: rec.id.into(),
+                post_id: rec.post_id,
+                kind: rec.kind,
+                timestamp: rec.timestamp,
+                events: rec.events.try_into().unwrap_or(0),
+            })
+            .collect())
+    }
+
+    #[tracing::instrument(skip_all)]
+    async fn erase_user_events(
+        &self,
+        user_id: &str,
+        mode: ErasureMode,
+    ) -> anyhow::Result<ErasureReport> {
+        let mut tx = self.pool.begin().await.map_err(|e| anyhow!(e))?;
+
+        if mode == ErasureMode::Delete {
+            sqlx::query!(
+                r#"
+                update post_counters as c
+                join (
+                    select
+                        post_id,
+                        sum(kind = 'view') as views,
+                        sum(kind = 'like') as likes,
+                        sum(kind = 'share') as shares
+                    from events
+                    where user_id = ?
+                    group by post_id
+                ) as e on e.post_id = c.post_id
+                set
+                    c.views = c.views - e.views,
+                    c.likes = c.likes - e.likes,
+                    c.shares = c.shares - e.shares
+                "#,
+                user_id
+            )
+            .execute(&mut *tx)
+            .await
+            .map_err(|e| anyhow!(e))?;
+        }
+
+        let (events, archived) = match mode {
+            ErasureMode::Delete => (
+                sqlx::query!("delete from events where user_id = ?", user_id)
+                    .execute(&mut *tx)
+                    .await,
+                sqlx::query!("delete from events_archive where user_id = ?", user_id)
+                    .execute(&mut *tx)
+                    .await,
+            ),
+            ErasureMode::Anonymize => (
+                sqlx::query!(
+                    "update events set user_id = null where user_id = ?",
+                    user_id
+                )
+                .execute(&mut *tx)
+                .await,
+                sqlx::query!(
+                    "update events_archive set user_id = null where user_id = ?",
+                    user_id
+                )
+                .execute(&mut *tx)
+                .await,
+            ),
+        };
+
+        let events = events.map_err(|e| anyhow!(e))?.rows_affected();
+        let archived = archived.map_err(|e| anyhow!(e))?.rows_affected();
+
+        tx.commit().await.map_err(|e| anyhow!(e))?;
+
+        Ok(ErasureReport {
+            mode,
+            events: events + archived,
+        })
+    }
+
+    #[tracing::instrument(skip_all)]
+    async fn purge_post_events(
+        &self,
+        post_id: &str,
+        mode: PurgeMode,
+    ) -> anyhow::Result<PurgeReport> {
+        let mut tx = self.pool.begin().await.map_err(|e| anyhow!(e))?;
+
+        if mode == PurgeMode::Archive {
+            sqlx::query!(
+                r#"
+                insert into events_archive
+                    (id, user_id, device_id, post_id, kind, referrer, timestamp)
+                select id, user_id, device_id, post_id, kind, referrer, timestamp
+                from events
+                where post_id = ?
+                "#,
+                post_id
+            )
+            .execute(&mut *tx)
+            .await
+            .map_err(|e| anyhow!(e))?;
+        }
+
+        let rec = sqlx::query!("delete from events where post_id = ?", post_id)
+            .execute(&mut *tx)
+            .await
+            .map_err(|e| anyhow!(e))?;
+
+        // Deleting also removes what earlier purges or retention archived.
+        let archived = if mode == PurgeMode::Delete {
+            sqlx::query!("delete from events_archive where post_id = ?", post_id)
+                .execute(&mut *tx)
+                .await
+                .map_err(|e| anyhow!(e))?
+                .rows_affected()
         } else {
-            None
+            0
         };
 
+        sqlx::query!(
+            "delete from event_daily_aggregates where post_id = ?",
+            post_id
+        )
+        .execute(&mut *tx)
+        .await
+        .map_err(|e| anyhow!(e))?;
+
+        sqlx::query!("delete from post_counters where post_id = ?", post_id)
+            .execute(&mut *tx)
+            .await
+            .map_err(|e| anyhow!(e))?;
+
+        sqlx::query!("delete from post_tags where post_id = ?", post_id)
+            .execute(&mut *tx)
+            .await
+            .map_err(|e| anyhow!(e))?;
+
+        sqlx::query!("delete from post_metadata where post_id = ?", post_id)
+            .execute(&mut *tx)
+            .await
+            .map_err(|e| anyhow!(e))?;
+
+        sqlx::query!(
+            "delete from related_posts where post_id = ? or related_post_id = ?",
+            post_id,
+            post_id
+        )
+        .execute(&mut *tx)
+        .await
+        .map_err(|e| anyhow!(e))?;
+
+        tx.commit().await.map_err(|e| anyhow!(e))?;
+
+        Ok(PurgeReport {
+            mode,
+            events: rec.rows_affected() + archived,
+        })
+    }
+
+    #[tracing::instrument(skip_all)]
+    async fn roll_up_events(
+        &self,
+        kind: EventKind,
+        before: DateTime<Utc>,
+        limit: u32,
+    ) -> anyhow::Result<u64> {
+        let mut tx = self.pool.begin().await.map_err(|e| anyhow!(e))?;
+
+        // Lock the batch first so concurrent roll-ups cannot count it twice;
+        // the roll-up and delete below then cover exactly these rows.
+        let ids = sqlx::query_scalar!(
+            r#"
+            select id
+            from events
+            where kind = ? and timestamp < ?
+            order by id
+            limit ?
+            for update
+            "#,
+            kind,
+            before,
+            limit
+        )
+        .fetch_all(&mut *tx)
+        .await
+        .map_err(|e| anyhow!(e))?;
+
+        let Some(last_id) = ids.last() else {
+            return Ok(0);
+        };
+
+        sqlx::query!(
+            r#"
+            insert into event_daily_aggregates (post_id, day, kind, total)
+            select post_id, date(timestamp), kind, count(*)
+            from events
+            where kind = ? and timestamp < ? and id <= ?
+            group by post_id, date(timestamp), kind
+            on duplicate key update total = total + values(total)
+            "#,
+            kind,
+            before,
+            last_id
+        )
+        .execute(&mut *tx)
+        .await
+        .map_err(|e| anyhow!(e))?;
+
+        let rec = sqlx::query!(
+            "delete from events where kind = ? and timestamp < ? and id <= ?",
+            kind,
+            before,
+            last_id
+        )
+        .execute(&mut *tx)
+        .await
+        .map_err(|e| anyhow!(e))?;
+
+        tx.commit().await.map_err(|e| anyhow!(e))?;
+
+        Ok(rec.rows_affected())
+    }
+
+    #[tracing::instrument(skip_all)]
+    async fn rebuild_counters(&self, post_id: Option<&str>) -> anyhow::Result<u64> {
+        let mut tx = self.pool.begin().await.map_err(|e| anyhow!(e))?;
+
+        sqlx::query!(
+            "delete from post_counters where ? is null or post_id = ?",
+            post_id,
+            post_id
+        )
+        .execute(&mut *tx)
+        .await
+        .map_err(|e| anyhow!(e))?;
+
         let rec = sqlx::query!(
             r#"
-            insert into events (user_id, device_id, post_id, kind, timestamp)
-            values (?, ?, ?, ?, ?)
+            insert into post_counters (post_id, views, likes, shares)
+            select
+                post_id,
+                sum(if(kind = 'view', n, 0)),
+                sum(if(kind = 'like', n, 0)),
+                sum(if(kind = 'share', n, 0))
+            from (
+                select post_id, kind, 1 as n
+                from events
+                where ? is null or post_id = ?
+                union all
+                select post_id, kind, total as n
+                from event_daily_aggregates
+                where ? is null or post_id = ?
+            ) as e
+            group by post_id
             "#,
-            user_id,
-            device_id,
-            event.post_id,
-            event.kind,
-            chrono::offset::Utc::now()
+            post_id,
+            post_id,
+            post_id,
+            post_id
         )
-        .execute(&self.pool)
+        .execute(&mut *tx)
         .await
         .map_err(|e| anyhow!(e))?;
 
-        Ok(rec.last_insert_id().try_into().unwrap())
+        tx.commit().await.map_err(|e| anyhow!(e))?;
+
+        Ok(rec.rows_affected())
     }
 
-    async fn find_devices(&self) -> anyhow::Result<Vec<Device>> {
-        Ok(sqlx::query_as!(Device, "select * from devices")
-            .fetch_all(&self.pool)
+    #[tracing::instrument(skip_all)]
+    async fn find_stale_post_metadata(
+        &self,
+        refreshed_before: DateTime<Utc>,
+        limit: u32,
+    ) -> anyhow::Result<Vec<String>> {
+        let recs = sqlx::query!(
+            r#"
+            select c.post_id
+            from post_counters c
+            left join post_metadata m on m.post_id = c.post_id
+            where m.post_id is null or m.refreshed_at < ?
+            order by m.refreshed_at
+            limit ?
+            "#,
+            refreshed_before,
+            limit
+        )
+        .fetch_all(&self.pool)
+        .await
+        .map_err(|e| anyhow!(e))?;
+
+        Ok(recs.into_iter().map(|rec| rec.post_id).collect())
+    }
+
+    #[tracing::instrument(skip_all)]
+    async fn find_author_post_ids(
+        &self,
+        author_id: &str,
+        limit: u32,
+    ) -> anyhow::Result<Vec<String>> {
+        let recs = sqlx::query!(
+            r#"
+            select post_id
+            from post_metadata
+            where author_id = ?
+            order by published_at desc, post_id
+            limit ?
+            "#,
+            author_id,
+            limit
+        )
+        .fetch_all(&self.pool)
+        .await
+        .map_err(|e| anyhow!(e))?;
+
+        Ok(recs.into_iter().map(|rec| rec.post_id).collect())
+    }
+
+    #[tracing::instrument(skip_all)]
+    async fn save_post_metadata(&self, post_id: &str, post: Option<&Post>) -> anyhow::Result<()> {
+        let mut tx = self.pool.begin().await.map_err(|e| anyhow!(e))?;
+
+        sqlx::query!(
+            r#"
+            insert into post_metadata (post_id, author_id, published_at, refreshed_at)
+            values (?, ?, ?, ?)
+            on duplicate key update
+                author_id = values(author_id),
+                published_at = values(published_at),
+                refreshed_at = values(refreshed_at)
+            "#,
+            post_id,
+            post.map(|p| &p.author_id),
+            post.and_then(|p| p.published_at),
+            Utc::now()
+        )
+        .execute(&mut *tx)
+        .await
+        .map_err(|e| anyhow!(e))?;
+
+        sqlx::query!("delete from post_tags where post_id = ?", post_id)
+            .execute(&mut *tx)
             .await
-            .map_err(|e| anyhow!(e))?)
+            .map_err(|e| anyhow!(e))?;
+
+        let tags: BTreeSet<&str> = post
+            .iter()
+            .flat_map(|p| &p.tags)
+            .map(|tag| tag.trim())
+            .filter(|tag| !tag.is_empty() && tag.chars().count() <= 255)
+            .collect();
+
+        if !tags.is_empty() {
+            QueryBuilder::<sqlx::MySql>::new("insert into post_tags (post_id, tag) ")
+                .push_values(tags, |mut row, tag| {
+                    row.push_bind(post_id).push_bind(tag);
+                })
+                .build()
+                .execute(&mut *tx)
+                .await
+                .map_err(|e| anyhow!(e))?;
+        }
+
+        tx.commit().await.map_err(|e| anyhow!(e))?;
+
+        Ok(())
+    }
+
+    #[tracing::instrument(skip_all)]
+    async fn find_tag_engagement(
+        &self,
+        from: NaiveDate,
+        to: NaiveDate,
+        limit: usize,
+    ) -> anyhow::Result<Vec<TagEngagement>> {
+        let start = from.and_time(NaiveTime::MIN).and_utc();
+        let end = to.and_time(NaiveTime::MIN).and_utc();
+
+        let recent = sqlx::query!(
+            r#"
+            select t.tag, e.kind as "kind: EventKind", count(*) as total
+            from events e
+            join post_tags t on t.post_id = e.post_id
+            where e.timestamp >= ? and e.timestamp < ?
+            group by t.tag, e.kind
+            "#,
+            start,
+            end
+        )
+        .fetch_all(&self.pool)
+        .await
+        .map_err(|e| anyhow!(e))?;
+
+        let rolled_up = sqlx::query!(
+            r#"
+            select t.tag, a.kind as "kind: EventKind", cast(sum(a.total) as signed) as "total!"
+            from event_daily_aggregates a
+            join post_tags t on t.post_id = a.post_id
+            where a.day >= ? and a.day < ?
+            group by t.tag, a.kind
+            "#,
+            from,
+            to
+        )
+        .fetch_all(&self.pool)
+        .await
+        .map_err(|e| anyhow!(e))?;
+
+        let rows = recent
+            .into_iter()
+            .map(|rec| (rec.tag, rec.kind, rec.total))
+            .chain(
+                rolled_up
+                    .into_iter()
+                    .map(|rec| (rec.tag, rec.kind, rec.total)),
+            );
+
+        Ok(rank_engagement(rows, limit)
+            .into_iter()
+            .map(|(tag, summary)| TagEngagement { tag, summary })
+            .collect())
+    }
+
+    #[tracing::instrument(skip_all)]
+    async fn find_author_engagement(
+        &self,
+        from: NaiveDate,
+        to: NaiveDate,
+        limit: usize,
+    ) -> anyhow::Result<Vec<AuthorEngagement>> {
+        let start = from.and_time(NaiveTime::MIN).and_utc();
+        let end = to.and_time(NaiveTime::MIN).and_utc();
+
+        let recent = sqlx::query!(
+            r#"
+            select m.author_id as "author_id!", e.kind as "kind: EventKind", count(*) as total
+            from events e
+            join post_metadata m on m.post_id = e.post_id
+            where m.author_id is not null and e.timestamp >= ? and e.timestamp < ?
+            group by m.author_id, e.kind
+            "#,
+            start,
+            end
+        )
+        .fetch_all(&self.pool)
+        .await
+        .map_err(|e| anyhow!(e))?;
+
+        let rolled_up = sqlx::query!(
+            r#"
+            select m.author_id as "author_id!", a.kind as "kind: EventKind", cast(sum(a.total) as signed) as "total!"
+            from event_daily_aggregates a
+            join post_metadata m on m.post_id = a.post_id
+            where m.author_id is not null and a.day >= ? and a.day < ?
+            group by m.author_id, a.kind
+            "#,
+            from,
+            to
+        )
+        .fetch_all(&self.pool)
+        .await
+        .map_err(|e| anyhow!(e))?;
+
+        let rows = recent
+            .into_iter()
+            .map(|rec| (rec.author_id, rec.kind, rec.total))
+            .chain(
+                rolled_up
+                    .into_iter()
+                    .map(|rec| (rec.author_id, rec.kind, rec.total)),
+            );
+
+        Ok(rank_engagement(rows, limit)
+            .into_iter()
+            .map(|(author_id, summary)| AuthorEngagement { author_id, summary })
+            .collect())
+    }
+
+    #[tracing::instrument(skip_all)]
+    async fn find_interactions(
+        &self,
+        since: DateTime<Utc>,
+        after: Option<&Interaction>,
+        limit: u32,
+    ) -> anyhow::Result<Vec<Interaction>> {
+        let (after_user, after_post, after_kind) = match after {
+            Some(after) => (
+                Some(after.user_id.as_str()),
+                Some(after.post_id.as_str()),
+                // MySQL numbers enum values from 1, in declaration order.
+                Some(after.kind as u8 + 1),
+            ),
+            None => (None, None, None),
+        };
+
+        // Keyset pagination over the (user_id, post_id, kind, id) index, which
+        // also serves the grouping. The row comparison is written out so that
+        // each column is compared as stored, which lets the index bound the
+        // range. An enum compared to a number compares by index, and by name
+        // when compared to a string, so kinds are bound by index.
+        Ok(sqlx::query_as!(
+            Interaction,
+            r#"
+            select user_id as "user_id!", post_id, kind as "kind: EventKind"
+            from events
+            where user_id is not null
+                and timestamp >= ?
+                and (
+                    ? is null
+                    or user_id > ?
+                    or (user_id = ? and (post_id > ? or (post_id = ? and kind > ?)))
+                )
+            group by user_id, post_id, kind
+            order by user_id, post_id, kind
+            limit ?
+            "#,
+            since,
+            after_user,
+            after_user,
+            after_user,
+            after_post,
+            after_post,
+            after_kind,
+            limit
+        )
+        .fetch_all(&self.pool)
+        .await
+        .map_err(|e| anyhow!(e))?)
+    }
+
+    #[tracing::instrument(skip_all)]
+    async fn replace_related_posts(
+        &self,
+        related: &BTreeMap<String, Vec<Recommendation>>,
+    ) -> anyhow::Result<()> {
+        let rows: Vec<(&String, &Recommendation)> = related
+            .iter()
+            .flat_map(|(post_id, posts)| posts.iter().map(move |post| (post_id, post)))
+            .collect();
+
+        let mut tx = self.pool.begin().await.map_err(|e| anyhow!(e))?;
+
+        sqlx::query!("delete from related_posts")
+            .execute(&mut *tx)
+            .await
+            .map_err(|e| anyhow!(e))?;
+
+        for chunk in rows.chunks(1000) {
+            QueryBuilder::<sqlx::MySql>::new(
+                "insert into related_posts (post_id, related_post_id, score) ",
+            )
+            .push_values(chunk, |mut row, (post_id, post)| {
+                row.push_bind(*post_id)
+                    .push_bind(&post.post_id)
+                    .push_bind(post.score);
+            })
+            .build()
+            .execute(&mut *tx)
+            .await
+            .map_err(|e| anyhow!(e))?;
+        }
+
+        tx.commit().await.map_err(|e| anyhow!(e))?;
+
+        Ok(())
+    }
+
+    #[tracing::instrument(skip_all)]
+    async fn find_related_posts(
+        &self,
+        post_id: &str,
+        limit: u32,
+    ) -> anyhow::Result<Vec<Recommendation>> {
+        Ok(sqlx::query_as!(
+            Recommendation,
+            r#"
+            select related_post_id as post_id, score
+            from related_posts
+            where post_id = ?
+            order by score desc, related_post_id
+            limit ?
+            "#,
+            post_id,
+            limit
+        )
+        .fetch_all(&self.pool)
+        .await
+        .map_err(|e| anyhow!(e))?)
+    }
+
+    #[tracing::instrument(skip_all)]
+    async fn find_recommendations(
+        &self,
+        user_id: &str,
+        limit: u32,
+    ) -> anyhow::Result<Vec<Recommendation>> {
+        Ok(sqlx::query_as!(
+            Recommendation,
+            r#"
+            select r.related_post_id as post_id, cast(sum(r.score) as double) as "score!"
+            from related_posts r
+            where r.post_id in (select post_id from events where user_id = ?)
+                and r.related_post_id not in (select post_id from events where user_id = ?)
+            group by r.related_post_id
+            order by sum(r.score) desc, r.related_post_id
+            limit ?
+            "#,
+            user_id,
+            user_id,
+            limit
+        )
+        .fetch_all(&self.pool)
+        .await
+        .map_err(|e| anyhow!(e))?)
     }
 }
@@ -1,4 +1,4 @@
-use chrono::{DateTime, Utc};
+use chrono::{DateTime, NaiveDate, Utc};
 use serde::{Deserialize, Serialize};
 use utoipa::ToSchema;
 
@@ -18,16 +18,84 @@ pub struct Event {
     pub user_id: Option<String>,
     pub post_id: String,
     pub kind: EventKind,
+    /// Host of the page the reader came from.
+    pub referrer: Option<String>,
     pub timestamp: DateTime<Utc>,
 }
 
-#[derive(Debug, Clone, Serialize, utoipa::ToSchema)]
+#[derive(Debug, Clone, Default, Serialize, utoipa::ToSchema)]
 pub struct EventSummary {
     pub views: usize,
     pub likes: usize,
     pub shares: usize,
 }
 
+/// Events of each kind recorded for a post on one day.
+#[derive(Debug, Clone, Default, Serialize, utoipa::ToSchema)]
+pub struct DailyEngagement {
+    pub day: NaiveDate,
+    pub views: u64,
+    pub likes: u64,
+    pub shares: u64,
+}
+
+/// How many events share a value, such as a browser or referrer.
+#[derive(Debug, Clone, Serialize, utoipa::ToSchema)]
+pub struct Breakdown {
+    pub value: String,
+    pub events: u64,
+}
+
+/// Engagement with a post, as shown to its author.
+///
+/// The summary covers the post's whole life. The rest covers the requested
+/// days; the breakdowns only count events that have not been rolled up yet.
+#[derive(Debug, Clone, Serialize, utoipa::ToSchema)]
+pub struct PostAnalytics {
+    pub post_id: String,
+    pub summary: EventSummary,
+    pub daily: Vec<DailyEngagement>,
+    pub operating_systems: Vec<Breakdown>,
+    pub browsers: Vec<Breakdown>,
+    pub referrers: Vec<Breakdown>,
+}
+
+/// A post suggested from the engagement of other readers. Higher scores are
+/// better; they are only comparable within one response.
+#[derive(Debug, Clone, PartialEq, Serialize, utoipa::ToSchema)]
+pub struct Recommendation {
+    pub post_id: String,
+    pub score: f64,
+}
+
+/// A post a user engaged with in one way, however many times they did.
+#[derive(Debug, Clone, Serialize, utoipa::ToSchema)]
+pub struct HistoryEntry {
+    /// The latest of the collapsed events, which orders the history.
+    #[serde(skip)]
+    pub id: i64,
+    pub post_id: String,
+    pub kind: EventKind,
+    /// When the user last engaged with the post this way.
+    pub timestamp: DateTime<Utc>,
+    /// How many events were collapsed into this entry.
+    pub events: u64,
+}
+
+/// Engagement with every post carrying a tag, over a range of days.
+#[derive(Debug, Clone, Serialize, utoipa::ToSchema)]
+pub struct TagEngagement {
+    pub tag: String,
+    pub summary: EventSummary,
+}
+
+/// Engagement with every post by an author, over a range of days.
+#[derive(Debug, Clone, Serialize, utoipa::ToSchema)]
+pub struct AuthorEngagement {
+    pub author_id: String,
+    pub summary: EventSummary,
+}
+
 #[derive(Debug, Clone, Serialize, utoipa::ToSchema)]
 pub struct Device {
     pub id: i64,
@@ -36,3 +104,61 @@ pub struct Device {
     pub language: Option<String>,
     pub screen_resolution: Option<String>,
 }
+
+/// How a user's events are erased when their account is deleted.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum ErasureMode {
+    /// Deletes the events, lowering the post summaries.
+    Delete,
+    /// Detaches the events from the user, preserving the post summaries.
+    #[default]
+    Anonymize,
+}
+
+impl std::str::FromStr for ErasureMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "delete" => Ok(Self::Delete),
+            "anonymize" => Ok(Self::Anonymize),
+            other => Err(format!("Unknown erasure mode: {other}")),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, utoipa::ToSchema)]
+pub struct ErasureReport {
+    pub mode: ErasureMode,
+    pub events: u64,
+}
+
+/// What happens to the events of a post removed from the articles service.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum PurgeMode {
+    /// Deletes the events.
+    #[default]
+    Delete,
+    /// Moves the events to `events_archive`.
+    Archive,
+}
+
+impl std::str::FromStr for PurgeMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "delete" => Ok(Self::Delete),
+            "archive" => Ok(Self::Archive),
+            other => Err(format!("Unknown purge mode: {other}")),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, utoipa::ToSchema)]
+pub struct PurgeReport {
+    pub mode: PurgeMode,
+    pub events: u64,
+}
@@ -0,0 +1,274 @@
+use std::{
+    collections::HashSet,
+    fs,
+    path::{Path, PathBuf},
+    sync::{Arc, RwLock},
+};
+
+use anyhow::{Context, anyhow};
+use serde::Deserialize;
+use subtle::ConstantTimeEq;
+
+use crate::http::ApiError;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+pub enum Scope {
+    #[serde(rename = "events:read")]
+    EventsRead,
+    #[serde(rename = "devices:read")]
+    DevicesRead,
+    #[serde(rename = "users:export")]
+    UsersExport,
+    #[serde(rename = "users:erase")]
+    UsersErase,
+    #[serde(rename = "posts:purge")]
+    PostsPurge,
+    #[serde(rename = "metrics:read")]
+    MetricsRead,
+    #[serde(rename = "admin")]
+    Admin,
+}
+
+impl Scope {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::EventsRead => "events:read",
+            Self::DevicesRead => "devices:read",
+            Self::UsersExport => "users:export",
+            Self::UsersErase => "users:erase",
+            Self::PostsPurge => "posts:purge",
+            Self::MetricsRead => "metrics:read",
+            Self::Admin => "admin",
+        }
+    }
+}
+
+/// A named key accepted by the private API through `X-Internal-Token`.
+#[derive(Debug, Clone, Deserialize)]
+pub struct ApiKey {
+    pub name: String,
+    pub token: String,
+    pub scopes: Vec<Scope>,
+}
+
+impl ApiKey {
+    pub fn principal(&self) -> Principal {
+        Principal {
+            name: self.name.clone(),
+            scopes: self.scopes.clone(),
+        }
+    }
+}
+
+/// Reads a JSON array of [`ApiKey`]s.
+pub fn load_api_keys(path: impl AsRef<Path>) -> anyhow::Result<Vec<ApiKey>> {
+    let path = path.as_ref();
+    let contents =
+        fs::read_to_string(path).with_context(|| format!("reading {}", path.display()))?;
+    serde_json::from_str(&contents)
+        .with_context(|| format!("parsing API keys from {}", path.display()))
+}
+
+/// The set of API keys accepted by the private API.
+///
+/// Keys come from an optional JSON file plus a list of `internal` admin tokens.
+/// Several keys may share a name, so a token can be rotated by adding the new
+/// one, moving clients over and then removing the old one. The file is re-read
+/// by [`ApiKeyStore::reload`] without restarting the server.
+pub struct ApiKeyStore {
+    file: Option<PathBuf>,
+    internal_tokens: Vec<String>,
+    keys: RwLock<Arc<Vec<ApiKey>>>,
+}
+
+impl ApiKeyStore {
+    pub fn new(file: Option<PathBuf>, internal_tokens: Vec<String>) -> anyhow::Result<Self> {
+        let keys = Self::load(file.as_deref(), &internal_tokens)?;
+
+        Ok(Self {
+            file,
+            internal_tokens,
+            keys: RwLock::new(Arc::new(keys)),
+        })
+    }
+
+    /// Accepts exactly `keys`, without a file to reload them from.
+    #[cfg(test)]
+    pub fn from_keys(keys: Vec<ApiKey>) -> Self {
+        Self {
+            file: None,
+            internal_tokens: Vec::new(),
+            keys: RwLock::new(Arc::new(keys)),
+        }
+    }
+
+    fn load(file: Option<&Path>, internal_tokens: &[String]) -> anyhow::Result<Vec<ApiKey>> {
+        let mut keys = match file {
+            Some(path) => load_api_keys(path)?,
+            None => Vec::new(),
+        };
+
+        keys.extend(internal_tokens.iter().map(|token| ApiKey {
+            name: "internal".to_owned(),
+            token: token.clone(),
+            scopes: vec![Scope::Admin],
+        }));
+
+        if keys.is_empty() {
+            return Err(anyhow!("No API keys configured"));
+        }
+
+        // An empty token would match a request sending an empty header.
+        if let Some(key) = keys.iter().find(|key| key.token.trim().is_empty()) {
+            return Err(anyhow!("API key {} has an empty token", key.name));
+        }
+
+        let mut tokens = HashSet::new();
+        if let Some(key) = keys.iter().find(|key| !tokens.insert(key.token.as_str())) {
+            return Err(anyhow!(
+                "API key {} reuses the token of another key",
+                key.name
+            ));
+        }
+
+        Ok(keys)
+    }
+
+    /// Re-reads the keys file, keeping the current keys if it is invalid.
+    ///
+    /// The `internal` tokens are kept as given to [`ApiKeyStore::new`]: they
+    /// come from the environment, which can't change while the process runs,
+    /// so rotating them needs a restart.
+    pub fn reload(&self) -> anyhow::Result<usize> {
+        let keys = Self::load(self.file.as_deref(), &self.internal_tokens)?;
+        let count = keys.len();
+        *self.keys.write().unwrap() = Arc::new(keys);
+        Ok(count)
+    }
+
+    /// Finds the key matching `token`.
+    ///
+    /// Every key is compared in constant time, so the response time does not
+    /// reveal how much of a token was correct.
+    pub fn authenticate(&self, token: &str) -> Option<Principal> {
+        let keys = self.keys.read().unwrap().clone();
+        let mut found = None;
+
+        for key in keys.iter() {
+            let matches = bool::from(key.token.as_bytes().ct_eq(token.as_bytes()));
+            if matches && found.is_none() {
+                found = Some(key.principal());
+            }
+        }
+
+        found
+    }
+}
+
+/// The caller of a private endpoint, as identified by its API key.
+#[derive(Debug, Clone)]
+pub struct Principal {
+    pub name: String,
+    pub scopes: Vec<Scope>,
+}
+
+impl Principal {
+    pub fn has_scope(&self, scope: Scope) -> bool {
+        self.scopes.contains(&Scope::Admin) || self.scopes.contains(&scope)
+    }
+
+    pub fn require(&self, scope: Scope) -> Result<(), ApiError> {
+        if self.has_scope(scope) {
+            Ok(())
+        } else {
+            Err(ApiError::Forbidden(Some(format!(
+                "Missing scope {}",
+                scope.as_str()
+            ))))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn authenticates_rotated_tokens() {
+        let store =
+            ApiKeyStore::new(None, vec!["current".to_owned(), "previous".to_owned()]).unwrap();
+
+        assert_eq!(store.authenticate("current").unwrap().name, "internal");
+        assert!(store.authenticate("previous").is_some());
+        assert!(store.authenticate("curren").is_none());
+        assert!(store.authenticate("").is_none());
+    }
+
+    #[test]
+    fn rejects_empty_and_duplicate_tokens() {
+        assert!(ApiKeyStore::new(None, vec!["".to_owned()]).is_err());
+        assert!(ApiKeyStore::new(None, vec!["  ".to_owned()]).is_err());
+        assert!(ApiKeyStore::new(None, vec!["a".to_owned(), "a".to_owned()]).is_err());
+
+        let path = std::env::temp_dir().join(format!("api-keys-empty-{}.json", std::process::id()));
+        fs::write(
+            &path,
+            r#"[{ "name": "blank", "token": "", "scopes": ["events:read"] }]"#,
+        )
+        .unwrap();
+        assert!(ApiKeyStore::new(Some(path.clone()), vec!["a".to_owned()]).is_err());
+
+        fs::write(
+            &path,
+            r#"[{ "name": "ok", "token": "b", "scopes": ["events:read"] }]"#,
+        )
+        .unwrap();
+        let store = ApiKeyStore::new(Some(path.clone()), vec!["a".to_owned()]).unwrap();
+
+        fs::write(
+            &path,
+            r#"[{ "name": "dup", "token": "a", "scopes": ["events:read"] }]"#,
+        )
+        .unwrap();
+        assert!(store.reload().is_err());
+        assert!(store.authenticate("b").is_some());
+        assert!(store.authenticate("").is_none());
+
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn reloads_keys_file() {
+        let path = std::env::temp_dir().join(format!("api-keys-{}.json", std::process::id()));
+        fs::write(
+            &path,
+            r#"[{ "name": "old", "token": "a", "scopes": ["events:read"] }]"#,
+        )
+        .unwrap();
+
+        let store = ApiKeyStore::new(Some(path.clone()), Vec::new()).unwrap();
+        let principal = store.authenticate("a").unwrap();
+        assert!(principal.has_scope(Scope::EventsRead));
+        assert!(!principal.has_scope(Scope::DevicesRead));
+
+        fs::write(
+            &path,
+            r#"[{ "name": "new", "token": "b", "scopes": ["admin"] }]"#,
+        )
+        .unwrap();
+        store.reload().unwrap();
+        assert!(store.authenticate("a").is_none());
+        assert!(
+            store
+                .authenticate("b")
+                .unwrap()
+                .has_scope(Scope::DevicesRead)
+        );
+
+        fs::write(&path, "not json").unwrap();
+        assert!(store.reload().is_err());
+        assert!(store.authenticate("b").is_some());
+
+        fs::remove_file(path).unwrap();
+    }
+}
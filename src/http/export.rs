@@ -0,0 +1,136 @@
+use std::collections::HashMap;
+
+use axum::{
+    body::{Body, Bytes},
+    http::header::{CONTENT_DISPOSITION, CONTENT_TYPE},
+    response::{IntoResponse, Response},
+};
+use futures_util::{StreamExt, TryStreamExt, stream};
+use serde::{Deserialize, Serialize};
+use utoipa::{IntoParams, ToSchema};
+
+use crate::{
+    db::EventRepository,
+    domain::{Device, Event, EventKind, EventSummary},
+    http::ApiResult,
+};
+
+#[derive(Debug, Clone, Copy, Default, Deserialize, ToSchema, clap::ValueEnum)]
+#[serde(rename_all = "snake_case")]
+pub enum ExportFormat {
+    #[default]
+    Json,
+    Csv,
+}
+
+#[derive(Debug, Clone, IntoParams, Deserialize)]
+#[into_params(parameter_in = Query)]
+pub struct ExportQuery {
+    #[serde(default)]
+    pub format: ExportFormat,
+}
+
+/// A row of the CSV export: an event with its device inlined.
+#[derive(Debug, Serialize)]
+struct CsvRow<'a> {
+    id: i64,
+    post_id: &'a str,
+    kind: EventKind,
+    referrer: Option<&'a str>,
+    timestamp: String,
+    os: Option<&'a str>,
+    browser: Option<&'a str>,
+    screen_resolution: Option<&'a str>,
+    language: Option<&'a str>,
+}
+
+/// Builds a streamed download of everything recorded for `user_id`.
+///
+/// The JSON export holds the user's summary, devices and events; the CSV export
+/// holds one row per event with its device inlined.
+pub async fn user_export(
+    repo: &dyn EventRepository,
+    user_id: String,
+    format: ExportFormat,
+) -> ApiResult<Response> {
+    let summary = repo.find_user_event_summary(&user_id).await?;
+    let devices = repo.find_user_devices(&user_id).await?;
+    let events = repo.stream_user_events(user_id.clone());
+
+    let (content_type, extension, body) = match format {
+        ExportFormat::Json => {
+            let head = json_head(&user_id, &summary, &devices)?;
+
+            let events = events.enumerate().map(|(i, event)| {
+                let event = serde_json::to_string(&event?)?;
+                let sep = if i == 0 { "" } else { "," };
+                Ok::<_, anyhow::Error>(Bytes::from(format!("{sep}{event}")))
+            });
+
+            let body = stream::once(async { Ok(Bytes::from(head)) })
+                .chain(events)
+                .chain(stream::once(async { Ok(Bytes::from_static(b"]}")) }));
+
+            ("application/json", "json", Body::from_stream(body))
+        }
+        ExportFormat::Csv => {
+            let devices: HashMap<i64, Device> = devices.into_iter().map(|d| (d.id, d)).collect();
+
+            let header = Bytes::from_static(
+                b"id,post_id,kind,referrer,timestamp,os,browser,screen_resolution,language\n",
+            );
+
+            let rows = events.and_then(move |event| {
+                let row = csv_row(&event, &devices);
+                async move { row }
+            });
+
+            let body = stream::once(async { Ok(header) }).chain(rows);
+
+            ("text/csv", "csv", Body::from_stream(body))
+        }
+    };
+
+    let disposition = format!("attachment; filename=\"engagement-export.{extension}\"");
+
+    Ok((
+        [
+            (CONTENT_TYPE, content_type.to_owned()),
+            (CONTENT_DISPOSITION, disposition),
+        ],
+        body,
+    )
+        .into_response())
+}
+
+/// Opens the JSON export object, up to the start of the streamed events array.
+fn json_head(user_id: &str, summary: &EventSummary, devices: &[Device]) -> anyhow::Result<String> {
+    Ok(format!(
+        r#"{{"user_id":{},"summary":{},"devices":{},"events":["#,
+        serde_json::to_string(user_id)?,
+        serde_json::to_string(summary)?,
+        serde_json::to_string(devices)?,
+    ))
+}
+
+fn csv_row(event: &Event, devices: &HashMap<i64, Device>) -> anyhow::Result<Bytes> {
+    let device = event.device_id.and_then(|id| devices.get(&id.into()));
+
+    let mut writer = csv::WriterBuilder::new()
+        .has_headers(false)
+        .from_writer(Vec::new());
+
+    writer.serialize(CsvRow {
+        id: event.id,
+        post_id: &event.post_id,
+        kind: event.kind,
+        referrer: event.referrer.as_deref(),
+        timestamp: event.timestamp.to_rfc3339(),
+        os: device.and_then(|d| d.os.as_deref()),
+        browser: device.and_then(|d| d.browser.as_deref()),
+        screen_resolution: device.and_then(|d| d.screen_resolution.as_deref()),
+        language: device.and_then(|d| d.language.as_deref()),
+    })?;
+
+    Ok(Bytes::from(writer.into_inner()?))
+}
@@ -6,7 +6,7 @@ use axum::{
 };
 
 use crate::{
-    http::{ApiError, state::AppState},
+    http::{ApiError, auth::Principal, state::AppState},
     users::User,
 };
 
@@ -62,3 +62,15 @@ impl OptionalFromRequestParts<Arc<AppState>> for RequestUser {
         }
     }
 }
+
+impl<S: Send + Sync> FromRequestParts<S> for Principal {
+    type Rejection = ApiError;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        parts
+            .extensions
+            .get::<Principal>()
+            .cloned()
+            .ok_or_else(|| ApiError::Unauthorized(None))
+    }
+}
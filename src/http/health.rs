@@ -0,0 +1,127 @@
+use std::time::{Duration, Instant};
+
+use axum::{Json, http::StatusCode, response::IntoResponse};
+use futures_util::future::join4;
+use serde::Serialize;
+use utoipa::ToSchema;
+
+use crate::http::state::AppState;
+
+/// How long a single dependency check may take before it counts as failed.
+const CHECK_TIMEOUT: Duration = Duration::from_secs(2);
+
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct DependencyReport {
+    pub name: &'static str,
+    pub healthy: bool,
+    pub latency_ms: u128,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct ReadinessReport {
+    pub ready: bool,
+    pub dependencies: Vec<DependencyReport>,
+}
+
+impl IntoResponse for ReadinessReport {
+    fn into_response(self) -> axum::response::Response {
+        let status = if self.ready {
+            StatusCode::OK
+        } else {
+            StatusCode::SERVICE_UNAVAILABLE
+        };
+
+        (status, Json(self)).into_response()
+    }
+}
+
+async fn check<F>(name: &'static str, check: F) -> DependencyReport
+where
+    F: Future<Output = anyhow::Result<()>>,
+{
+    let start = Instant::now();
+
+    // The details stay in the logs, as the probe is public.
+    let error = match tokio::time::timeout(CHECK_TIMEOUT, check).await {
+        Ok(Ok(())) => None,
+        Ok(Err(e)) => {
+            tracing::warn!("Readiness check of {name} failed: {e:?}");
+            Some(format!("{name} is unavailable"))
+        }
+        Err(_) => {
+            tracing::warn!("Readiness check of {name} timed out after {CHECK_TIMEOUT:?}");
+            Some(format!("{name} timed out"))
+        }
+    };
+
+    DependencyReport {
+        name,
+        healthy: error.is_none(),
+        latency_ms: start.elapsed().as_millis(),
+        error,
+    }
+}
+
+/// Checks every dependency concurrently.
+pub async fn readiness(state: &AppState) -> ReadinessReport {
+    let migrations = async {
+        let pending = state
+            .repo
+            .migration_status()
+            .await?
+            .into_iter()
+            .filter(|m| !m.applied)
+            .count();
+
+        if pending == 0 {
+            Ok(())
+        } else {
+            Err(anyhow::anyhow!("{pending} pending migrations"))
+        }
+    };
+
+    let (database, migrations, users, articles) = join4(
+        check("database", state.repo.ping()),
+        check("migrations", migrations),
+        check("users", state.users.ping()),
+        check("articles", state.posts.ping()),
+    )
+    .await;
+
+    let dependencies = vec![database, migrations, users, articles];
+
+    ReadinessReport {
+        ready: dependencies.iter().all(|d| d.healthy),
+        dependencies,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn failures_hide_their_details() {
+        let report = check("database", async {
+            anyhow::bail!("Access denied for user 'root'")
+        })
+        .await;
+
+        assert!(!report.healthy);
+        assert_eq!(report.error.as_deref(), Some("database is unavailable"));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn slow_checks_time_out() {
+        let report = check("users", async {
+            tokio::time::sleep(CHECK_TIMEOUT * 2).await;
+            Ok(())
+        })
+        .await;
+
+        assert!(!report.healthy);
+        assert_eq!(report.error.as_deref(), Some("users timed out"));
+    }
+}
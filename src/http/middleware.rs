@@ -1,23 +1,28 @@
+use std::collections::HashMap;
+use std::convert::Infallible;
 use std::sync::Arc;
 use std::task::{Context, Poll};
+use std::time::Instant;
 
+use axum::extract::MatchedPath;
+use axum::http::{Method, StatusCode, header::ACCESS_CONTROL_REQUEST_METHOD};
 use axum::response::IntoResponse;
 use axum::{body::Body, http::Request, response::Response};
 use futures_util::future::BoxFuture;
-use tower::{Layer, Service};
+use tower::{Layer, Service, ServiceExt, service_fn};
+use tower_http::cors::CorsLayer;
 
-use crate::http::ApiError;
+use crate::http::{ApiError, auth::ApiKeyStore};
+use crate::metrics::METRICS;
 
 #[derive(Clone)]
 pub struct InternalAuthLayer {
-    token: Arc<String>,
+    keys: Arc<ApiKeyStore>,
 }
 
 impl InternalAuthLayer {
-    pub fn new<T: Into<String>>(token: T) -> Self {
-        Self {
-            token: Arc::new(token.into()),
-        }
+    pub fn new(keys: Arc<ApiKeyStore>) -> Self {
+        Self { keys }
     }
 }
 
@@ -27,7 +32,7 @@ impl<S> Layer<S> for InternalAuthLayer {
     fn layer(&self, inner: S) -> Self::Service {
         InternalAuth {
             inner,
-            token: self.token.clone(),
+            keys: self.keys.clone(),
         }
     }
 }
@@ -35,7 +40,7 @@ impl<S> Layer<S> for InternalAuthLayer {
 #[derive(Clone)]
 pub struct InternalAuth<S> {
     inner: S,
-    token: Arc<String>,
+    keys: Arc<ApiKeyStore>,
 }
 
 impl<S> Service<Request<Body>> for InternalAuth<S>
@@ -52,17 +57,19 @@ where
         self.inner.poll_ready(cx)
     }
 
-    fn call(&mut self, req: Request<Body>) -> Self::Future {
-        let token = self.token.clone();
+    fn call(&mut self, mut req: Request<Body>) -> Self::Future {
+        let keys = self.keys.clone();
         let mut inner = self.inner.clone();
 
         Box::pin(async move {
-            let received_token = req
+            let principal = req
                 .headers()
                 .get("X-Internal-Token")
-                .and_then(|h| h.to_str().ok());
+                .and_then(|h| h.to_str().ok())
+                .and_then(|t| keys.authenticate(t));
 
-            if received_token.is_some_and(|t| t == *token) {
+            if let Some(principal) = principal {
+                req.extensions_mut().insert(principal);
                 inner.call(req).await
             } else {
                 Ok(ApiError::Unauthorized(None).into_response())
@@ -70,3 +77,161 @@ where
         })
     }
 }
+
+/// Records request counts and latencies per route and status.
+#[derive(Clone)]
+pub struct MetricsLayer;
+
+impl<S> Layer<S> for MetricsLayer {
+    type Service = RequestMetrics<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        RequestMetrics { inner }
+    }
+}
+
+#[derive(Clone)]
+pub struct RequestMetrics<S> {
+    inner: S,
+}
+
+impl<S> Service<Request<Body>> for RequestMetrics<S>
+where
+    S: Service<Request<Body>, Response = Response> + Send + Clone + 'static,
+    S::Future: Send + 'static,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = BoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, req: Request<Body>) -> Self::Future {
+        let method = req.method().to_string();
+        // Label by route template rather than the raw path to bound cardinality.
+        let route = req
+            .extensions()
+            .get::<MatchedPath>()
+            .map(|p| p.as_str().to_owned())
+            .unwrap_or_else(|| "unmatched".to_owned());
+        let start = Instant::now();
+        let future = self.inner.call(req);
+
+        Box::pin(async move {
+            let res = future.await?;
+            let status = res.status().as_u16().to_string();
+            let labels = [method.as_str(), route.as_str(), status.as_str()];
+
+            METRICS.http_requests.with_label_values(&labels).inc();
+            METRICS
+                .http_request_duration
+                .with_label_values(&labels)
+                .observe(start.elapsed().as_secs_f64());
+
+            Ok(res)
+        })
+    }
+}
+
+/// Answers CORS preflight requests to paths served by both the private and the
+/// public routers, like `/events`, with the policy of the router serving the
+/// requested method. Otherwise the router merged last would answer them all.
+#[derive(Clone)]
+pub struct PreflightLayer {
+    shared: Arc<SharedPaths>,
+}
+
+struct SharedPaths {
+    /// The private methods of each shared path.
+    private_methods: HashMap<String, Vec<Method>>,
+    private: Option<CorsLayer>,
+    public: Option<CorsLayer>,
+}
+
+impl PreflightLayer {
+    pub fn new(
+        private_methods: HashMap<String, Vec<Method>>,
+        private: Option<CorsLayer>,
+        public: Option<CorsLayer>,
+    ) -> Self {
+        Self {
+            shared: Arc::new(SharedPaths {
+                private_methods,
+                private,
+                public,
+            }),
+        }
+    }
+}
+
+impl<S> Layer<S> for PreflightLayer {
+    type Service = Preflight<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        Preflight {
+            inner,
+            shared: self.shared.clone(),
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct Preflight<S> {
+    inner: S,
+    shared: Arc<SharedPaths>,
+}
+
+impl<S> Service<Request<Body>> for Preflight<S>
+where
+    S: Service<Request<Body>, Response = Response> + Send + Clone + 'static,
+    S::Future: Send + 'static,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = BoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, req: Request<Body>) -> Self::Future {
+        if req.method() != Method::OPTIONS {
+            return Box::pin(self.inner.call(req));
+        }
+
+        let requested = req
+            .headers()
+            .get(ACCESS_CONTROL_REQUEST_METHOD)
+            .and_then(|m| Method::from_bytes(m.as_bytes()).ok());
+        let private_methods = req
+            .extensions()
+            .get::<MatchedPath>()
+            .and_then(|path| self.shared.private_methods.get(path.as_str()));
+
+        let (Some(requested), Some(private_methods)) = (requested, private_methods) else {
+            return Box::pin(self.inner.call(req));
+        };
+
+        let cors = if private_methods.contains(&requested) {
+            self.shared.private.clone()
+        } else {
+            self.shared.public.clone()
+        };
+
+        Box::pin(async move {
+            let Some(cors) = cors else {
+                return Ok(StatusCode::METHOD_NOT_ALLOWED.into_response());
+            };
+
+            // The CORS layer answers preflight requests without calling its
+            // inner service.
+            let empty = service_fn(|_| async { Ok::<_, Infallible>(Response::new(Body::empty())) });
+            match cors.layer(empty).oneshot(req).await {
+                Ok(res) => Ok(res),
+                Err(never) => match never {},
+            }
+        })
+    }
+}
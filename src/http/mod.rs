@@ -1,5 +1,9 @@
+pub mod auth;
+pub mod export;
 pub mod extractors;
+pub mod health;
 pub mod middleware;
+pub mod request_id;
 pub mod routes;
 pub mod state;
 
@@ -10,7 +14,10 @@ use serde::{Serialize, Serializer};
 use thiserror::Error;
 use utoipa::ToSchema;
 
-use crate::http::state::AppState;
+use crate::{
+    config::Config,
+    http::{auth::ApiKeyStore, state::AppState},
+};
 
 pub type ApiResult<T> = Result<T, ApiError>;
 
@@ -27,6 +34,12 @@ pub enum ApiError {
 
     #[error("Unauthorized")]
     Unauthorized(Option<String>),
+
+    #[error("Forbidden")]
+    Forbidden(Option<String>),
+
+    #[error("Service Unavailable")]
+    ServiceUnavailable(Option<String>),
 }
 
 #[derive(Debug, Clone, Serialize, ToSchema)]
@@ -37,6 +50,9 @@ pub struct StatusResponse {
     title: String,
     #[serde(skip_serializing_if = "Option::is_none")]
     detail: Option<String>,
+    /// ID of the request, to correlate the response with server logs.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    request_id: Option<String>,
 }
 
 impl StatusResponse {
@@ -45,6 +61,7 @@ impl StatusResponse {
             status,
             title: status.to_string(),
             detail: None,
+            request_id: request_id::current(),
         }
     }
 
@@ -53,6 +70,7 @@ impl StatusResponse {
             status,
             title: status.to_string(),
             detail,
+            request_id: request_id::current(),
         }
     }
 }
@@ -83,10 +101,16 @@ impl IntoResponse for ApiError {
             Self::Unauthorized(detail) => {
                 StatusResponse::with_detail(StatusCode::UNAUTHORIZED, detail).into_response()
             }
+            Self::Forbidden(detail) => {
+                StatusResponse::with_detail(StatusCode::FORBIDDEN, detail).into_response()
+            }
+            Self::ServiceUnavailable(detail) => {
+                StatusResponse::with_detail(StatusCode::SERVICE_UNAVAILABLE, detail).into_response()
+            }
         }
     }
 }
 
-pub fn app(secret_token: &str) -> axum::Router<Arc<AppState>> {
-    routes::build_router(secret_token)
+pub fn app(api_keys: Arc<ApiKeyStore>, config: &Config) -> axum::Router<Arc<AppState>> {
+    routes::build_router(api_keys, config)
 }
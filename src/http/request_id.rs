@@ -0,0 +1,102 @@
+use std::task::{Context, Poll};
+
+use axum::{
+    body::Body,
+    http::{HeaderName, HeaderValue, Request, Response},
+};
+use futures_util::future::BoxFuture;
+use tower::{Layer, Service};
+use uuid::Uuid;
+
+pub static X_REQUEST_ID: HeaderName = HeaderName::from_static("x-request-id");
+
+tokio::task_local! {
+    static REQUEST_ID: String;
+}
+
+pub fn current() -> Option<String> {
+    REQUEST_ID.try_with(Clone::clone).ok()
+}
+
+pub fn forward(request: reqwest::RequestBuilder) -> reqwest::RequestBuilder {
+    match current() {
+        Some(id) => request.header(X_REQUEST_ID.as_str(), id),
+        None => request,
+    }
+}
+
+/// Reuses the caller's `X-Request-Id` or generates one.
+#[derive(Clone)]
+pub struct RequestIdLayer;
+
+impl<S> Layer<S> for RequestIdLayer {
+    type Service = RequestId<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        RequestId { inner }
+    }
+}
+
+#[derive(Clone)]
+pub struct RequestId<S> {
+    inner: S,
+}
+
+impl<S, B> Service<Request<Body>> for RequestId<S>
+where
+    S: Service<Request<Body>, Response = Response<B>> + Send + Clone + 'static,
+    S::Future: Send + 'static,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = BoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, mut req: Request<Body>) -> Self::Future {
+        let id = req
+            .headers()
+            .get(&X_REQUEST_ID)
+            .and_then(|value| value.to_str().ok())
+            .filter(|id| is_valid(id))
+            .map(str::to_owned)
+            .unwrap_or_else(|| Uuid::new_v4().to_string());
+
+        // Both values are visible ASCII, so they are valid header values.
+        let header = HeaderValue::from_str(&id).unwrap();
+        req.headers_mut().insert(&X_REQUEST_ID, header.clone());
+
+        let future = REQUEST_ID.scope(id, self.inner.call(req));
+
+        Box::pin(async move {
+            let mut res = future.await?;
+            res.headers_mut().insert(&X_REQUEST_ID, header);
+            Ok(res)
+        })
+    }
+}
+
+fn is_valid(id: &str) -> bool {
+    !id.is_empty() && id.len() <= 128 && id.bytes().all(|b| b.is_ascii_graphic())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn accepts_reasonable_ids() {
+        assert!(is_valid("3f2c9a1e-7d4b-4f5a-9c1e-2b8d6a0f4e71"));
+        assert!(is_valid("req_123"));
+    }
+
+    #[test]
+    fn rejects_empty_long_or_unprintable_ids() {
+        assert!(!is_valid(""));
+        assert!(!is_valid(&"a".repeat(129)));
+        assert!(!is_valid("has space"));
+        assert!(!is_valid("new\nline"));
+    }
+}
@@ -1,41 +1,94 @@
-use std::sync::Arc;
+use std::{collections::HashMap, sync::Arc};
 
 use axum::{
     Json,
     extract::{Path, Query, State},
-    http::StatusCode,
-    response::IntoResponse,
+    http::{HeaderName, HeaderValue, Method, StatusCode, header::CONTENT_TYPE},
+    response::{
+        IntoResponse, Response,
+        sse::{self, KeepAlive, Sse},
+    },
+    routing::get,
 };
-use serde::Deserialize;
+use chrono::{Days, NaiveDate, Utc};
+use futures_util::{Stream, StreamExt, TryStreamExt, stream};
+use reqwest::Url;
+use serde::{Deserialize, Serialize};
 use tower::ServiceBuilder;
-use tower_http::{cors::CorsLayer, trace::TraceLayer};
-use utoipa::{IntoParams, OpenApi};
+use tower_http::{
+    cors::{AllowHeaders, AllowMethods, AllowOrigin, Any, CorsLayer},
+    trace::TraceLayer,
+};
+use utoipa::{IntoParams, OpenApi, ToSchema};
 use utoipa_axum::{router::OpenApiRouter, routes};
 use utoipa_swagger_ui::SwaggerUi;
 
 use crate::{
-    db::CreateEventRequest,
-    domain::{Device, Event, EventSummary},
+    config::{Config, CorsPolicy},
+    db::{CreateEventRequest, MigrationReport, NewEvent},
+    domain::{
+        AuthorEngagement, Device, ErasureMode, ErasureReport, Event, EventKind, EventSummary,
+        HistoryEntry, PostAnalytics, PurgeMode, PurgeReport, Recommendation, TagEngagement,
+    },
     http::{
-        ApiError, ApiResult, StatusResponse, extractors::RequestUser,
-        middleware::InternalAuthLayer, state::AppState,
+        ApiError, ApiResult, StatusResponse,
+        auth::{ApiKeyStore, Principal, Scope},
+        export::{self, ExportQuery},
+        extractors::RequestUser,
+        health::{self, ReadinessReport},
+        middleware::{InternalAuthLayer, MetricsLayer, PreflightLayer},
+        request_id::{RequestIdLayer, X_REQUEST_ID},
+        state::AppState,
     },
+    metrics::METRICS,
+    telemetry,
 };
+use tracing_opentelemetry::OpenTelemetrySpanExt;
 
 async fn get_not_found(State(_state): State<Arc<AppState>>) -> impl IntoResponse {
     StatusResponse::new(StatusCode::NOT_FOUND)
 }
 
 #[utoipa::path(get, path = "/", description = "Returns a status message", responses((status = OK, body = StatusResponse)))]
+#[tracing::instrument(skip_all)]
 async fn get_hello(State(_state): State<Arc<AppState>>) -> impl IntoResponse {
     StatusResponse::with_detail(StatusCode::OK, Some("It works!".to_owned()))
 }
 
+#[utoipa::path(get, path = "/healthz", description = "Liveness probe, succeeds while the server is running", responses((status = OK, body = StatusResponse)))]
+#[tracing::instrument(skip_all)]
+async fn get_healthz() -> impl IntoResponse {
+    StatusResponse::new(StatusCode::OK)
+}
+
+#[utoipa::path(get, path = "/readyz", description = "Readiness probe, checks the database, migrations and upstream services", responses((status = OK, body = ReadinessReport), (status = SERVICE_UNAVAILABLE, body = ReadinessReport)))]
+#[tracing::instrument(skip_all)]
+async fn get_readyz(State(state): State<Arc<AppState>>) -> ReadinessReport {
+    health::readiness(&state).await
+}
+
+async fn get_metrics(
+    State(state): State<Arc<AppState>>,
+    principal: Principal,
+) -> ApiResult<Response> {
+    principal.require(Scope::MetricsRead)?;
+
+    Ok((
+        [(CONTENT_TYPE, "text/plain; version=0.0.4")],
+        METRICS.render(state.repo.pool_stats()),
+    )
+        .into_response())
+}
+
 #[utoipa::path(get, path = "/events", params(EventQuery), description = "Returns all events", responses((status = OK, body = [Event])))]
+#[tracing::instrument(skip_all)]
 async fn get_events(
     Query(query): Query<EventQuery>,
     State(state): State<Arc<AppState>>,
+    principal: Principal,
 ) -> ApiResult<Json<Vec<Event>>> {
+    principal.require(Scope::EventsRead)?;
+
     let events = state
         .repo
         .find_events(&query.user_id, &query.post_id)
@@ -44,6 +97,7 @@ async fn get_events(
 }
 
 #[utoipa::path(get, path = "/events/{post_id}", description = "Gets a summary of events for the given post.", responses((status = OK, body = EventSummary)))]
+#[tracing::instrument(skip_all)]
 async fn get_event_summary(
     Path(post_id): Path<String>,
     State(state): State<Arc<AppState>>,
@@ -52,8 +106,40 @@ async fn get_event_summary(
     Ok(Json(summary))
 }
 
+#[utoipa::path(get, path = "/events/{post_id}/stream", description = "Streams the summary of the given post as Server-Sent Events: once on connect and again whenever events are recorded for it", responses((status = OK, description = "`summary` events carrying an EventSummary", content_type = "text/event-stream"), (status = BAD_REQUEST, body = StatusResponse), (status = UNAUTHORIZED, body = StatusResponse), (status = SERVICE_UNAVAILABLE, body = StatusResponse)))]
+#[tracing::instrument(skip_all)]
+async fn get_event_stream(
+    Path(post_id): Path<String>,
+    State(state): State<Arc<AppState>>,
+    _user: RequestUser,
+) -> ApiResult<Sse<impl Stream<Item = Result<sse::Event, axum::Error>>>> {
+    if !state.posts.validate_post_id(&post_id).await? {
+        return Err(ApiError::BadRequest(Some("Invalid post ID".to_owned())));
+    }
+
+    // Subscribe first so that no update is lost while reading the summary.
+    let updates = state
+        .live
+        .subscribe(&post_id)
+        .ok_or_else(|| ApiError::ServiceUnavailable(Some("Too many open streams".to_owned())))?
+        .into_stream();
+    let summary = state.repo.find_event_summary(&post_id).await?;
+
+    let events = stream::once(async { summary })
+        .chain(updates)
+        .map(|summary| sse::Event::default().event("summary").json_data(summary));
+
+    Ok(Sse::new(events).keep_alive(KeepAlive::default()))
+}
+
 #[utoipa::path(get, path = "/devices", params(EventQuery), description = "Returns all recorded devices", responses((status = OK, body = [Device])))]
-async fn get_devices(State(state): State<Arc<AppState>>) -> ApiResult<Json<Vec<Device>>> {
+#[tracing::instrument(skip_all)]
+async fn get_devices(
+    State(state): State<Arc<AppState>>,
+    principal: Principal,
+) -> ApiResult<Json<Vec<Device>>> {
+    principal.require(Scope::DevicesRead)?;
+
     let events = state.repo.find_devices().await?;
     Ok(Json(events))
 }
@@ -65,7 +151,8 @@ struct EventQuery {
     user_id: Option<String>,
 }
 
-#[utoipa::path(post, path = "/events", request_body = CreateEventRequest, description = "Records a new event", responses((status = CREATED, body = StatusResponse)))]
+#[utoipa::path(post, path = "/events", request_body = CreateEventRequest, description = "Queues a new event to be recorded", responses((status = CREATED, body = StatusResponse), (status = SERVICE_UNAVAILABLE, body = StatusResponse)))]
+#[tracing::instrument(skip_all)]
 async fn create_event(
     State(state): State<Arc<AppState>>,
     user: Option<RequestUser>,
@@ -77,10 +164,479 @@ async fn create_event(
 
     let user_id = user.map(|RequestUser(user)| user.id);
 
-    state.repo.create_event(event, user_id).await?;
+    state.ingest.push(NewEvent {
+        post_id: event.post_id,
+        kind: event.kind,
+        device: event.device,
+        user_id,
+        referrer: event.referrer.as_deref().and_then(referrer_host),
+        timestamp: chrono::Utc::now(),
+    })?;
     Ok(StatusResponse::new(StatusCode::CREATED))
 }
 
+/// Keeps only the host of a referrer URL, which is what gets reported.
+fn referrer_host(referrer: &str) -> Option<String> {
+    let url = Url::parse(referrer).ok()?;
+    let host = url.host_str()?;
+    (host.len() <= 255).then(|| host.to_owned())
+}
+
+/// How many posts can be reported on in one request.
+const MAX_ANALYTICS_POSTS: usize = 50;
+
+/// How many posts are looked up and reported on at once.
+const ANALYTICS_CONCURRENCY: usize = 8;
+
+/// Resolves the range of days to report on: the last 30 days by default, and
+/// at most a year. `to` is inclusive, while the returned end is exclusive.
+fn report_days(
+    from: Option<NaiveDate>,
+    to: Option<NaiveDate>,
+) -> ApiResult<(NaiveDate, NaiveDate)> {
+    let out_of_range = || ApiError::BadRequest(Some("Dates are out of range".to_owned()));
+
+    let to = to.unwrap_or_else(|| Utc::now().date_naive());
+    let from = match from {
+        Some(from) => from,
+        None => to
+            .checked_sub_days(Days::new(29))
+            .ok_or_else(out_of_range)?,
+    };
+    if from > to || (to - from).num_days() >= 366 {
+        return Err(ApiError::BadRequest(Some(
+            "`from` must be before `to` and at most a year apart".to_owned(),
+        )));
+    }
+
+    let until = to.checked_add_days(Days::new(1)).ok_or_else(out_of_range)?;
+    Ok((from, until))
+}
+
+#[derive(Debug, Clone, IntoParams, Deserialize)]
+#[into_params(parameter_in = Query)]
+struct PostsAnalyticsQuery {
+    /// Comma separated IDs of the posts to report on. Defaults to the latest
+    /// posts of the current user, which requires the post catalog.
+    post_ids: Option<String>,
+    /// First day to report on. Defaults to 30 days before `to`.
+    from: Option<NaiveDate>,
+    /// Last day to report on, inclusive. Defaults to today.
+    to: Option<NaiveDate>,
+}
+
+#[utoipa::path(get, path = "/me/posts/analytics", params(PostsAnalyticsQuery), description = "Reports engagement with the given posts, or the latest posts of the current user, skipping those they did not author and those the articles service failed to return", responses((status = OK, body = [PostAnalytics]), (status = BAD_REQUEST, body = StatusResponse)))]
+#[tracing::instrument(skip_all)]
+async fn get_my_posts_analytics(
+    Query(query): Query<PostsAnalyticsQuery>,
+    State(state): State<Arc<AppState>>,
+    RequestUser(user): RequestUser,
+) -> ApiResult<Json<Vec<PostAnalytics>>> {
+    let (from, until) = report_days(query.from, query.to)?;
+
+    let post_ids: Vec<String> = match &query.post_ids {
+        Some(post_ids) => {
+            let mut post_ids: Vec<String> = post_ids
+                .split(',')
+                .map(str::trim)
+                .filter(|id| !id.is_empty())
+                .map(str::to_owned)
+                .collect();
+            post_ids.sort_unstable();
+            post_ids.dedup();
+
+            if post_ids.is_empty() || post_ids.len() > MAX_ANALYTICS_POSTS {
+                return Err(ApiError::BadRequest(Some(format!(
+                    "Between 1 and {MAX_ANALYTICS_POSTS} post IDs are required"
+                ))));
+            }
+            post_ids
+        }
+        // Authors are only known from the post metadata the catalog syncs.
+        None if !state.catalog_enabled => {
+            return Err(ApiError::BadRequest(Some(
+                "`post_ids` is required while the post catalog is disabled".to_owned(),
+            )));
+        }
+        None => {
+            state
+                .repo
+                .find_author_post_ids(&user.id, MAX_ANALYTICS_POSTS as u32)
+                .await?
+        }
+    };
+
+    let reports: Vec<Option<PostAnalytics>> = stream::iter(post_ids)
+        .map(|post_id| {
+            let (state, user_id) = (&state, &user.id);
+            async move {
+                let post = match state.posts.fetch_post(&post_id).await {
+                    Ok(post) => post,
+                    Err(e) => {
+                        tracing::warn!("Skipping post {post_id} from the analytics: {e:?}");
+                        return Ok(None);
+                    }
+                };
+                if post.is_none_or(|post| &post.author_id != user_id) {
+                    return Ok(None);
+                }
+
+                let report = state
+                    .repo
+                    .find_post_analytics(&post_id, from, until)
+                    .await?;
+                Ok::<_, ApiError>(Some(report))
+            }
+        })
+        .buffered(ANALYTICS_CONCURRENCY)
+        .try_collect()
+        .await?;
+
+    Ok(Json(reports.into_iter().flatten().collect()))
+}
+
+#[derive(Debug, Clone, IntoParams, Deserialize)]
+#[into_params(parameter_in = Query)]
+struct RecommendationQuery {
+    /// How many posts to return, at most 50. Defaults to 10.
+    limit: Option<u32>,
+}
+
+impl RecommendationQuery {
+    fn limit(&self) -> ApiResult<u32> {
+        match self.limit {
+            None => Ok(10),
+            Some(limit @ 1..=50) => Ok(limit),
+            Some(_) => Err(ApiError::BadRequest(Some(
+                "`limit` must be between 1 and 50".to_owned(),
+            ))),
+        }
+    }
+}
+
+#[utoipa::path(get, path = "/posts/{post_id}/related", params(RecommendationQuery), description = "Lists posts often engaged with by readers of the given post, best first", responses((status = OK, body = [Recommendation]), (status = BAD_REQUEST, body = StatusResponse)))]
+#[tracing::instrument(skip_all)]
+async fn get_related_posts(
+    Path(post_id): Path<String>,
+    Query(query): Query<RecommendationQuery>,
+    State(state): State<Arc<AppState>>,
+) -> ApiResult<Json<Vec<Recommendation>>> {
+    let related = state
+        .repo
+        .find_related_posts(&post_id, query.limit()?)
+        .await?;
+    Ok(Json(related))
+}
+
+#[utoipa::path(get, path = "/me/recommendations", params(RecommendationQuery), description = "Suggests posts related to those the current user engaged with, leaving out the ones they already read", responses((status = OK, body = [Recommendation]), (status = BAD_REQUEST, body = StatusResponse)))]
+#[tracing::instrument(skip_all)]
+async fn get_my_recommendations(
+    Query(query): Query<RecommendationQuery>,
+    State(state): State<Arc<AppState>>,
+    RequestUser(user): RequestUser,
+) -> ApiResult<Json<Vec<Recommendation>>> {
+    let recommendations = state
+        .repo
+        .find_recommendations(&user.id, query.limit()?)
+        .await?;
+    Ok(Json(recommendations))
+}
+
+#[derive(Debug, Clone, IntoParams, Deserialize)]
+#[into_params(parameter_in = Query)]
+struct HistoryQuery {
+    /// Only returns events of this kind.
+    kind: Option<EventKind>,
+    /// The `next_cursor` of the previous page.
+    cursor: Option<String>,
+    /// How many entries to return, at most 100. Defaults to 20.
+    limit: Option<u32>,
+}
+
+/// A page of the current user's history.
+#[derive(Debug, Clone, Serialize, ToSchema)]
+struct HistoryPage {
+    entries: Vec<HistoryEntry>,
+    /// Pass as `cursor` to get the next page. Absent on the last page.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    next_cursor: Option<String>,
+}
+
+#[utoipa::path(get, path = "/me/history", params(HistoryQuery), description = "Lists the posts the current user engaged with, newest first. Repeated events of a kind on a post are collapsed into one entry", responses((status = OK, body = HistoryPage), (status = BAD_REQUEST, body = StatusResponse)))]
+#[tracing::instrument(skip_all)]
+async fn get_my_history(
+    Query(query): Query<HistoryQuery>,
+    State(state): State<Arc<AppState>>,
+    RequestUser(user): RequestUser,
+) -> ApiResult<Json<HistoryPage>> {
+    let limit = match query.limit {
+        None => 20,
+        Some(limit @ 1..=100) => limit,
+        Some(_) => {
+            return Err(ApiError::BadRequest(Some(
+                "`limit` must be between 1 and 100".to_owned(),
+            )));
+        }
+    };
+
+    let before = query
+        .cursor
+        .as_deref()
+        .map(str::parse::<i64>)
+        .transpose()
+        .map_err(|_| ApiError::BadRequest(Some("Invalid cursor".to_owned())))?;
+
+    // Fetch one more entry than requested to know whether there is a next page.
+    let mut entries = state
+        .repo
+        .find_user_history(&user.id, query.kind, before, limit + 1)
+        .await?;
+
+    let next_cursor = if entries.len() > limit as usize {
+        entries.truncate(limit as usize);
+        entries.last().map(|entry| entry.id.to_string())
+    } else {
+        None
+    };
+
+    Ok(Json(HistoryPage {
+        entries,
+        next_cursor,
+    }))
+}
+
+#[utoipa::path(get, path = "/me/export", params(ExportQuery), description = "Exports every event, device and summary recorded for the current user", responses((status = OK, content((String = "application/json"), (String = "text/csv")))))]
+#[tracing::instrument(skip_all)]
+async fn get_my_export(
+    Query(query): Query<ExportQuery>,
+    State(state): State<Arc<AppState>>,
+    RequestUser(user): RequestUser,
+) -> ApiResult<Response> {
+    export::user_export(state.repo.as_ref(), user.id, query.format).await
+}
+
+#[utoipa::path(get, path = "/users/{user_id}/export", params(ExportQuery), description = "Exports every event, device and summary recorded for the given user", responses((status = OK, content((String = "application/json"), (String = "text/csv")))))]
+#[tracing::instrument(skip_all)]
+async fn get_user_export(
+    Path(user_id): Path<String>,
+    Query(query): Query<ExportQuery>,
+    State(state): State<Arc<AppState>>,
+    principal: Principal,
+) -> ApiResult<Response> {
+    principal.require(Scope::UsersExport)?;
+    export::user_export(state.repo.as_ref(), user_id, query.format).await
+}
+
+/// Writes the queued events, which would otherwise be written after erasing
+/// or purging them.
+async fn flush_queued_events(state: &AppState) -> ApiResult<()> {
+    if state.ingest.flush().await {
+        Ok(())
+    } else {
+        Err(ApiError::ServiceUnavailable(Some(
+            "Queued events could not be written, try again later".to_owned(),
+        )))
+    }
+}
+
+#[derive(Debug, Clone, IntoParams, Deserialize)]
+#[into_params(parameter_in = Query)]
+struct ErasureQuery {
+    /// Overrides the configured erasure mode.
+    mode: Option<ErasureMode>,
+}
+
+#[utoipa::path(delete, path = "/users/{user_id}/events", params(ErasureQuery), description = "Deletes or anonymizes every event recorded for the given user, including those still queued", responses((status = OK, body = ErasureReport), (status = SERVICE_UNAVAILABLE, body = StatusResponse)))]
+#[tracing::instrument(skip_all)]
+async fn delete_user_events(
+    Path(user_id): Path<String>,
+    Query(query): Query<ErasureQuery>,
+    State(state): State<Arc<AppState>>,
+    principal: Principal,
+) -> ApiResult<Json<ErasureReport>> {
+    principal.require(Scope::UsersErase)?;
+
+    flush_queued_events(&state).await?;
+
+    let mode = query.mode.unwrap_or(state.erasure_mode);
+    let report = state.repo.erase_user_events(&user_id, mode).await?;
+
+    tracing::info!(
+        "Erased events of user {} ({:?}): {} rows",
+        user_id,
+        report.mode,
+        report.events
+    );
+    Ok(Json(report))
+}
+
+#[derive(Debug, Clone, IntoParams, Deserialize)]
+#[into_params(parameter_in = Query)]
+struct PurgeQuery {
+    /// Overrides the configured purge mode.
+    mode: Option<PurgeMode>,
+}
+
+#[utoipa::path(delete, path = "/posts/{post_id}/events", params(PurgeQuery), description = "Deletes or archives every event recorded for the given post, including those still queued", responses((status = OK, body = PurgeReport), (status = SERVICE_UNAVAILABLE, body = StatusResponse)))]
+#[tracing::instrument(skip_all)]
+async fn delete_post_events(
+    Path(post_id): Path<String>,
+    Query(query): Query<PurgeQuery>,
+    State(state): State<Arc<AppState>>,
+    principal: Principal,
+) -> ApiResult<Json<PurgeReport>> {
+    principal.require(Scope::PostsPurge)?;
+
+    flush_queued_events(&state).await?;
+
+    let mode = query.mode.unwrap_or(state.purge_mode);
+    let report = state.repo.purge_post_events(&post_id, mode).await?;
+
+    tracing::info!(
+        "Purged events of post {} ({:?}): {} rows",
+        post_id,
+        report.mode,
+        report.events
+    );
+    Ok(Json(report))
+}
+
+#[derive(Debug, Clone, IntoParams, Deserialize)]
+#[into_params(parameter_in = Query)]
+struct EngagementRankingQuery {
+    /// First day to report on. Defaults to 30 days before `to`.
+    from: Option<NaiveDate>,
+    /// Last day to report on, inclusive. Defaults to today.
+    to: Option<NaiveDate>,
+    /// How many entries to return, at most 500. Defaults to 50.
+    limit: Option<usize>,
+}
+
+impl EngagementRankingQuery {
+    fn limit(&self) -> ApiResult<usize> {
+        match self.limit {
+            None => Ok(50),
+            Some(limit @ 1..=500) => Ok(limit),
+            Some(_) => Err(ApiError::BadRequest(Some(
+                "`limit` must be between 1 and 500".to_owned(),
+            ))),
+        }
+    }
+}
+
+#[utoipa::path(get, path = "/analytics/tags", params(EngagementRankingQuery), description = "Ranks post tags by the events recorded on their posts. Tags are refreshed from the articles service periodically", responses((status = OK, body = [TagEngagement]), (status = BAD_REQUEST, body = StatusResponse)))]
+#[tracing::instrument(skip_all)]
+async fn get_tag_analytics(
+    Query(query): Query<EngagementRankingQuery>,
+    State(state): State<Arc<AppState>>,
+    principal: Principal,
+) -> ApiResult<Json<Vec<TagEngagement>>> {
+    principal.require(Scope::EventsRead)?;
+
+    let limit = query.limit()?;
+    let (from, until) = report_days(query.from, query.to)?;
+
+    let tags = state.repo.find_tag_engagement(from, until, limit).await?;
+    Ok(Json(tags))
+}
+
+#[utoipa::path(get, path = "/analytics/authors", params(EngagementRankingQuery), description = "Ranks authors by the events recorded on their posts. Authors are refreshed from the articles service periodically", responses((status = OK, body = [AuthorEngagement]), (status = BAD_REQUEST, body = StatusResponse)))]
+#[tracing::instrument(skip_all)]
+async fn get_author_analytics(
+    Query(query): Query<EngagementRankingQuery>,
+    State(state): State<Arc<AppState>>,
+    principal: Principal,
+) -> ApiResult<Json<Vec<AuthorEngagement>>> {
+    principal.require(Scope::EventsRead)?;
+
+    let limit = query.limit()?;
+    let (from, until) = report_days(query.from, query.to)?;
+
+    let authors = state
+        .repo
+        .find_author_engagement(from, until, limit)
+        .await?;
+    Ok(Json(authors))
+}
+
+#[derive(Debug, Clone, Deserialize, ToSchema)]
+struct ArticlesWebhook {
+    /// Only `post.deleted` is handled, other events are ignored.
+    event: String,
+    post_id: String,
+}
+
+#[utoipa::path(post, path = "/webhooks/articles", request_body = ArticlesWebhook, description = "Receives notifications from the articles service", responses((status = OK, body = StatusResponse), (status = SERVICE_UNAVAILABLE, body = StatusResponse)))]
+#[tracing::instrument(skip_all)]
+async fn articles_webhook(
+    State(state): State<Arc<AppState>>,
+    principal: Principal,
+    Json(webhook): Json<ArticlesWebhook>,
+) -> ApiResult<StatusResponse> {
+    principal.require(Scope::PostsPurge)?;
+
+    if webhook.event != "post.deleted" {
+        return Ok(StatusResponse::with_detail(
+            StatusCode::OK,
+            Some(format!("Ignored event {}", webhook.event)),
+        ));
+    }
+
+    flush_queued_events(&state).await?;
+
+    let report = state
+        .repo
+        .purge_post_events(&webhook.post_id, state.purge_mode)
+        .await?;
+
+    tracing::info!(
+        "Purged events of deleted post {} ({:?}): {} rows",
+        webhook.post_id,
+        report.mode,
+        report.events
+    );
+    Ok(StatusResponse::new(StatusCode::OK))
+}
+
+#[derive(Debug, Clone, IntoParams, Deserialize)]
+#[into_params(parameter_in = Query)]
+struct RebuildCountersQuery {
+    /// Only rebuilds the counters of this post.
+    post_id: Option<String>,
+}
+
+#[utoipa::path(post, path = "/admin/counters/rebuild", params(RebuildCountersQuery), description = "Recomputes the post counters from the recorded events", responses((status = OK, body = StatusResponse)))]
+#[tracing::instrument(skip_all)]
+async fn rebuild_counters(
+    Query(query): Query<RebuildCountersQuery>,
+    State(state): State<Arc<AppState>>,
+    principal: Principal,
+) -> ApiResult<StatusResponse> {
+    principal.require(Scope::Admin)?;
+
+    let posts = state
+        .repo
+        .rebuild_counters(query.post_id.as_deref())
+        .await?;
+
+    Ok(StatusResponse::with_detail(
+        StatusCode::OK,
+        Some(format!("Rebuilt counters of {posts} posts")),
+    ))
+}
+
+#[utoipa::path(get, path = "/admin/migrations", description = "Lists applied and pending migrations", responses((status = OK, body = MigrationReport)))]
+#[tracing::instrument(skip_all)]
+async fn get_migrations(
+    State(state): State<Arc<AppState>>,
+    principal: Principal,
+) -> ApiResult<Json<MigrationReport>> {
+    principal.require(Scope::Admin)?;
+
+    let migrations = state.repo.migration_status().await?;
+    Ok(Json(migrations.into()))
+}
+
 #[derive(OpenApi)]
 #[openapi(info(
     title = "Mittel Engagement",
@@ -89,16 +645,150 @@ async fn create_event(
 ))]
 struct ApiDoc;
 
-pub fn build_router(secret_token: &str) -> axum::Router<Arc<AppState>> {
-    let private_router = OpenApiRouter::with_openapi(ApiDoc::openapi())
+fn request_span(req: &axum::http::Request<axum::body::Body>) -> tracing::Span {
+    let request_id = req
+        .headers()
+        .get(&X_REQUEST_ID)
+        .and_then(|id| id.to_str().ok())
+        .unwrap_or_default();
+
+    let span = tracing::info_span!(
+        "request",
+        method = %req.method(),
+        uri = %req.uri(),
+        request_id,
+        otel.kind = "server",
+    );
+    // Fails only when spans are not being exported, which is fine to ignore.
+    span.set_parent(telemetry::extract_context(req.headers()))
+        .ok();
+    span
+}
+
+fn cors_layer(policy: &CorsPolicy) -> Option<CorsLayer> {
+    if !policy.enabled {
+        return None;
+    }
+
+    // Values were validated when loading the configuration.
+    let origins: AllowOrigin = match policy.origins.as_slice() {
+        [] => Any.into(),
+        origins => origins
+            .iter()
+            .filter_map(|o| o.parse::<HeaderValue>().ok())
+            .collect::<Vec<_>>()
+            .into(),
+    };
+    let methods: AllowMethods = match policy.methods.as_slice() {
+        [] => Any.into(),
+        methods => methods
+            .iter()
+            .filter_map(|m| m.parse::<Method>().ok())
+            .collect::<Vec<_>>()
+            .into(),
+    };
+    let headers: AllowHeaders = match policy.headers.as_slice() {
+        [] => Any.into(),
+        headers => headers
+            .iter()
+            .filter_map(|h| h.parse::<HeaderName>().ok())
+            .collect::<Vec<_>>()
+            .into(),
+    };
+
+    Some(
+        CorsLayer::new()
+            .allow_origin(origins)
+            .allow_methods(methods)
+            .allow_headers(headers)
+            .allow_credentials(policy.credentials),
+    )
+}
+
+/// Lists the private methods of each path served by both routers.
+fn shared_private_methods<S>(
+    private: &OpenApiRouter<S>,
+    public: &OpenApiRouter<S>,
+) -> HashMap<String, Vec<Method>>
+where
+    S: Send + Sync + Clone + 'static,
+{
+    let public = &public.get_openapi().paths.paths;
+
+    private
+        .get_openapi()
+        .paths
+        .paths
+        .iter()
+        .filter(|(path, _)| public.contains_key(*path))
+        .map(|(path, item)| {
+            let methods = [
+                (Method::GET, &item.get),
+                (Method::POST, &item.post),
+                (Method::PUT, &item.put),
+                (Method::PATCH, &item.patch),
+                (Method::DELETE, &item.delete),
+            ]
+            .into_iter()
+            .filter(|(_, operation)| operation.is_some())
+            .map(|(method, _)| method)
+            .collect();
+
+            (path.clone(), methods)
+        })
+        .collect()
+}
+
+pub fn build_router(api_keys: Arc<ApiKeyStore>, config: &Config) -> axum::Router<Arc<AppState>> {
+    let mut private_router = OpenApiRouter::with_openapi(ApiDoc::openapi())
         .routes(routes!(get_events))
         .routes(routes!(get_devices))
-        .route_layer(InternalAuthLayer::new(secret_token));
+        .routes(routes!(get_tag_analytics))
+        .routes(routes!(get_author_analytics))
+        .routes(routes!(get_user_export))
+        .routes(routes!(delete_user_events))
+        .routes(routes!(delete_post_events))
+        .routes(routes!(rebuild_counters))
+        .routes(routes!(get_migrations));
+
+    if config.features.articles_webhook {
+        private_router = private_router.routes(routes!(articles_webhook));
+    }
+
+    // Left out of the API docs, like the docs themselves.
+    if config.features.metrics {
+        private_router = private_router.route("/metrics", get(get_metrics));
+    }
+
+    let private_router = private_router.route_layer(InternalAuthLayer::new(api_keys));
 
     let public_router = OpenApiRouter::with_openapi(ApiDoc::openapi())
         .routes(routes!(get_hello))
+        .routes(routes!(get_healthz))
+        .routes(routes!(get_readyz))
         .routes(routes!(get_event_summary))
-        .routes(routes!(create_event));
+        .routes(routes!(get_event_stream))
+        .routes(routes!(create_event))
+        .routes(routes!(get_my_export))
+        .routes(routes!(get_my_history))
+        .routes(routes!(get_related_posts))
+        .routes(routes!(get_my_recommendations))
+        .routes(routes!(get_my_posts_analytics));
+
+    let preflight = PreflightLayer::new(
+        shared_private_methods(&private_router, &public_router),
+        cors_layer(&config.cors.private),
+        cors_layer(&config.cors.public),
+    );
+
+    let private_router = match cors_layer(&config.cors.private) {
+        Some(cors) => private_router.layer(cors),
+        None => private_router,
+    };
+    let public_router = match cors_layer(&config.cors.public) {
+        Some(cors) => public_router.layer(cors),
+        None => public_router,
+    };
 
     let (router, api) = OpenApiRouter::with_openapi(ApiDoc::openapi())
         // Private endpoints
@@ -107,11 +797,636 @@ pub fn build_router(secret_token: &str) -> axum::Router<Arc<AppState>> {
         .fallback(get_not_found)
         .layer(
             ServiceBuilder::new()
-                .layer(TraceLayer::new_for_http())
-                .layer(CorsLayer::permissive()),
+                .layer(RequestIdLayer)
+                .layer(TraceLayer::new_for_http().make_span_with(request_span))
+                .layer(MetricsLayer)
+                .layer(preflight),
         )
         .split_for_parts();
 
-    let swagger = SwaggerUi::new("/docs").url("/openapi.json", api);
-    router.merge(swagger)
+    let mut router = router;
+
+    if config.features.docs {
+        router = router.merge(SwaggerUi::new("/docs").url("/openapi.json", api));
+    }
+
+    router
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::{body::Body, http::Request};
+    use tower::ServiceExt;
+
+    use super::*;
+    use crate::{
+        db::{DeviceRequest, EventRepository},
+        testing::{self, api_key},
+    };
+
+    fn app() -> axum::Router {
+        let keys = vec![
+            api_key("reader", vec![Scope::EventsRead]),
+            api_key("admin", vec![Scope::Admin]),
+        ];
+        testing::app(Arc::default(), keys, &Config::default()).router
+    }
+
+    async fn get(router: axum::Router, uri: &str, token: Option<&str>) -> StatusCode {
+        let mut request = Request::get(uri);
+        if let Some(token) = token {
+            request = request.header("X-Internal-Token", token);
+        }
+
+        let response = router
+            .oneshot(request.body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+        response.status()
+    }
+
+    async fn post_event(router: axum::Router) -> StatusCode {
+        let request = Request::post("/events")
+            .header("Content-Type", "application/json")
+            .body(Body::from(r#"{ "post_id": "1234567890", "kind": "view" }"#))
+            .unwrap();
+
+        router.oneshot(request).await.unwrap().status()
+    }
+
+    #[tokio::test]
+    async fn private_routes_require_a_key_with_their_scope() {
+        assert_eq!(get(app(), "/devices", None).await, StatusCode::UNAUTHORIZED);
+        assert_eq!(
+            get(app(), "/devices", Some("unknown")).await,
+            StatusCode::UNAUTHORIZED
+        );
+        assert_eq!(
+            get(app(), "/devices", Some("reader")).await,
+            StatusCode::FORBIDDEN
+        );
+        assert_eq!(get(app(), "/events", Some("reader")).await, StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn metrics_need_a_key_and_are_measured() {
+        assert_eq!(get(app(), "/metrics", None).await, StatusCode::UNAUTHORIZED);
+        assert_eq!(
+            get(app(), "/metrics", Some("reader")).await,
+            StatusCode::FORBIDDEN
+        );
+
+        let request = Request::get("/metrics")
+            .header("X-Internal-Token", "admin")
+            .body(Body::empty())
+            .unwrap();
+        let response = app().oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert!(response.headers().contains_key("x-request-id"));
+
+        let mut config = Config::default();
+        config.features.metrics = false;
+        let router = testing::app(Arc::default(), Vec::new(), &config).router;
+        assert_eq!(get(router, "/metrics", None).await, StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn articles_webhook_can_be_disabled() {
+        let webhook = |config: &Config| {
+            let router = testing::app(
+                Arc::default(),
+                vec![api_key("articles", vec![Scope::PostsPurge])],
+                config,
+            )
+            .router;
+            let request = Request::post("/webhooks/articles")
+                .header("X-Internal-Token", "articles")
+                .header("Content-Type", "application/json")
+                .body(Body::from(
+                    r#"{ "event": "post.deleted", "post_id": "1234567890" }"#,
+                ))
+                .unwrap();
+            router.oneshot(request)
+        };
+
+        let mut config = Config::default();
+        assert_eq!(webhook(&config).await.unwrap().status(), StatusCode::OK);
+
+        config.features.articles_webhook = false;
+        assert_eq!(
+            webhook(&config).await.unwrap().status(),
+            StatusCode::NOT_FOUND
+        );
+    }
+
+    #[tokio::test]
+    async fn erasure_includes_queued_events() {
+        let repo = Arc::new(testing::MemoryRepository::default());
+        let mut config = Config::default();
+        config.ingest.flush_interval_ms = 60 * 60 * 1000;
+        let router = testing::app(
+            repo.clone(),
+            vec![api_key("admin", vec![Scope::Admin])],
+            &config,
+        )
+        .router;
+
+        let request = Request::post("/events")
+            .header("Authorization", "Bearer 1234567890")
+            .header("Content-Type", "application/json")
+            .body(Body::from(r#"{ "post_id": "1234567890", "kind": "like" }"#))
+            .unwrap();
+        let response = router.clone().oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::CREATED);
+        assert_eq!(repo.event_count(), 0);
+
+        let request = Request::delete("/users/1234567890/events?mode=delete")
+            .header("X-Internal-Token", "admin")
+            .body(Body::empty())
+            .unwrap();
+        let response = router.oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(repo.event_count(), 0);
+        assert_eq!(repo.batches.lock().unwrap().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn purging_includes_queued_events() {
+        for uri in ["/posts/1234567890/events", "/webhooks/articles"] {
+            let repo = Arc::new(testing::MemoryRepository::default());
+            let mut config = Config::default();
+            config.ingest.flush_interval_ms = 60 * 60 * 1000;
+            let router = testing::app(
+                repo.clone(),
+                vec![api_key("admin", vec![Scope::Admin])],
+                &config,
+            )
+            .router;
+
+            assert_eq!(post_event(router.clone()).await, StatusCode::CREATED);
+
+            let request = if uri.starts_with("/webhooks") {
+                Request::post(uri)
+                    .header("Content-Type", "application/json")
+                    .body(Body::from(
+                        r#"{ "event": "post.deleted", "post_id": "1234567890" }"#,
+                    ))
+            } else {
+                Request::delete(uri).body(Body::empty())
+            }
+            .unwrap();
+            let (mut parts, body) = request.into_parts();
+            parts
+                .headers
+                .insert("X-Internal-Token", HeaderValue::from_static("admin"));
+            let response = router
+                .oneshot(Request::from_parts(parts, body))
+                .await
+                .unwrap();
+            assert_eq!(response.status(), StatusCode::OK, "{uri}");
+
+            let summary = repo.find_event_summary("1234567890").await.unwrap();
+            assert_eq!(summary.views, 0, "{uri}");
+            assert_eq!(repo.batches.lock().unwrap().len(), 1, "{uri}");
+        }
+    }
+
+    #[tokio::test]
+    async fn readiness_fails_without_the_details_while_liveness_holds() {
+        let repo = Arc::new(testing::MemoryRepository::default());
+        let router = testing::app(repo.clone(), Vec::new(), &Config::default()).router;
+        assert_eq!(get(router.clone(), "/readyz", None).await, StatusCode::OK);
+
+        repo.set_unreachable(true);
+
+        let (status, report) = get_json(router.clone(), "/readyz").await;
+        assert_eq!(status, StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(report["ready"], false);
+        let database = &report["dependencies"][0];
+        assert_eq!(database["healthy"], false);
+        assert_eq!(database["error"], "database is unavailable");
+        assert!(!report.to_string().contains("db.internal"));
+
+        assert_eq!(get(router, "/healthz", None).await, StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn admin_keys_pass_every_scope() {
+        assert_eq!(get(app(), "/devices", Some("admin")).await, StatusCode::OK);
+        assert_eq!(
+            get(app(), "/admin/migrations", Some("admin")).await,
+            StatusCode::OK
+        );
+        assert_eq!(
+            get(app(), "/admin/migrations", Some("reader")).await,
+            StatusCode::FORBIDDEN
+        );
+    }
+
+    #[tokio::test]
+    async fn rejects_events_when_the_buffer_is_full() {
+        let repo = Arc::new(testing::MemoryRepository::default());
+        repo.fail_writes(u32::MAX);
+        let mut config = Config::default();
+        config.ingest.buffer_size = 1;
+        config.ingest.batch_size = 1;
+        let router = testing::app(repo, Vec::new(), &config).router;
+
+        let mut statuses = Vec::new();
+        for _ in 0..5 {
+            statuses.push(post_event(router.clone()).await);
+        }
+
+        assert_eq!(statuses[0], StatusCode::CREATED);
+        assert!(statuses.contains(&StatusCode::SERVICE_UNAVAILABLE));
+    }
+
+    async fn preflight(
+        router: axum::Router,
+        uri: &str,
+        method: &str,
+        origin: &str,
+    ) -> Option<String> {
+        let request = Request::options(uri)
+            .header("Origin", origin)
+            .header("Access-Control-Request-Method", method)
+            .body(Body::empty())
+            .unwrap();
+
+        let response = router.oneshot(request).await.unwrap();
+        response
+            .headers()
+            .get("Access-Control-Allow-Origin")
+            .map(|origin| origin.to_str().unwrap().to_owned())
+    }
+
+    #[tokio::test]
+    async fn preflight_follows_the_router_of_the_requested_method() {
+        let mut config = Config::default();
+        config.cors.private.enabled = true;
+        config.cors.private.origins = vec!["https://admin.mittel.dev".to_owned()];
+        let router = testing::app(Arc::default(), Vec::new(), &config).router;
+
+        let admin = "https://admin.mittel.dev";
+        let blog = "https://mittel.dev";
+
+        // `GET /events` is private and `POST /events` public.
+        assert_eq!(
+            preflight(router.clone(), "/events", "GET", admin)
+                .await
+                .as_deref(),
+            Some(admin)
+        );
+        assert_eq!(
+            preflight(router.clone(), "/events", "GET", blog).await,
+            None
+        );
+        assert_eq!(
+            preflight(router.clone(), "/events", "POST", blog)
+                .await
+                .as_deref(),
+            Some("*")
+        );
+        assert_eq!(preflight(router, "/devices", "GET", blog).await, None);
+    }
+
+    fn date(s: &str) -> NaiveDate {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn report_days_end_the_day_after_to() {
+        assert_eq!(
+            report_days(None, Some(date("2026-10-18"))).unwrap(),
+            (date("2026-09-19"), date("2026-10-19"))
+        );
+        assert_eq!(
+            report_days(Some(date("2025-10-17")), Some(date("2026-10-17"))).unwrap(),
+            (date("2025-10-17"), date("2026-10-18"))
+        );
+    }
+
+    #[test]
+    fn report_days_reject_invalid_ranges() {
+        let bad_request = |result: ApiResult<_>| matches!(result, Err(ApiError::BadRequest(_)));
+
+        assert!(bad_request(report_days(
+            Some(date("2026-10-19")),
+            Some(date("2026-10-18"))
+        )));
+        assert!(bad_request(report_days(
+            Some(date("2025-10-16")),
+            Some(date("2026-10-17"))
+        )));
+        // Neither end may leave the range of representable dates.
+        assert!(bad_request(report_days(None, Some(NaiveDate::MAX))));
+        assert!(bad_request(report_days(None, Some(NaiveDate::MIN))));
+        assert!(bad_request(report_days(
+            Some(NaiveDate::MAX),
+            Some(NaiveDate::MAX)
+        )));
+    }
+
+    #[tokio::test]
+    async fn analytics_reject_dates_out_of_range() {
+        assert_eq!(
+            get(app(), "/analytics/tags?to=+262142-12-31", Some("reader")).await,
+            StatusCode::BAD_REQUEST
+        );
+        assert_eq!(
+            get(app(), "/analytics/authors?to=+262142-12-31", Some("reader")).await,
+            StatusCode::BAD_REQUEST
+        );
+    }
+
+    async fn get_json(router: axum::Router, uri: &str) -> (StatusCode, serde_json::Value) {
+        let request = Request::get(uri)
+            .header("Authorization", "Bearer 1234567890")
+            .body(Body::empty())
+            .unwrap();
+
+        let response = router.oneshot(request).await.unwrap();
+        let status = response.status();
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        (status, serde_json::from_slice(&body).unwrap_or_default())
+    }
+
+    #[tokio::test]
+    async fn post_analytics_skip_posts_that_failed_to_load() {
+        let (status, reports) = get_json(
+            app(),
+            "/me/posts/analytics?post_ids=1234567890,unavailable,short,0987654321",
+        )
+        .await;
+
+        assert_eq!(status, StatusCode::OK);
+        let post_ids: Vec<_> = reports
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|report| report["post_id"].as_str().unwrap())
+            .collect();
+        assert_eq!(post_ids, ["0987654321", "1234567890"]);
+    }
+
+    #[tokio::test]
+    async fn own_post_analytics_need_the_catalog() {
+        let mut config = Config::default();
+        config.features.catalog = false;
+        let router = testing::app(Arc::default(), Vec::new(), &config).router;
+
+        assert_eq!(
+            get_json(router.clone(), "/me/posts/analytics").await.0,
+            StatusCode::BAD_REQUEST
+        );
+        assert_eq!(
+            get_json(router, "/me/posts/analytics?post_ids=1234567890")
+                .await
+                .0,
+            StatusCode::OK
+        );
+        assert_eq!(
+            get_json(app(), "/me/posts/analytics").await.0,
+            StatusCode::OK
+        );
+    }
+
+    fn read(post_id: &str, kind: EventKind) -> NewEvent {
+        NewEvent {
+            post_id: post_id.to_owned(),
+            kind,
+            device: None,
+            user_id: Some("1234567890".to_owned()),
+            referrer: None,
+            timestamp: Utc::now(),
+        }
+    }
+
+    #[tokio::test]
+    async fn pages_through_the_history() {
+        let repo = Arc::new(testing::MemoryRepository::default());
+        repo.create_events(&[
+            read("a", EventKind::View),
+            read("b", EventKind::View),
+            read("a", EventKind::Like),
+            read("c", EventKind::View),
+            read("a", EventKind::View),
+        ])
+        .await
+        .unwrap();
+        let router = testing::app(repo, Vec::new(), &Config::default()).router;
+
+        let (status, page) = get_json(router.clone(), "/me/history?limit=2").await;
+        assert_eq!(status, StatusCode::OK);
+        let posts: Vec<_> = page["entries"].as_array().unwrap().iter().collect();
+        assert_eq!(posts.len(), 2);
+        assert_eq!(
+            (&posts[0]["post_id"], &posts[0]["events"]),
+            (&"a".into(), &2.into())
+        );
+        assert_eq!(posts[1]["post_id"], "c");
+
+        let cursor = page["next_cursor"].as_str().unwrap();
+        let (_, page) = get_json(
+            router.clone(),
+            &format!("/me/history?limit=2&cursor={cursor}"),
+        )
+        .await;
+        let posts: Vec<_> = page["entries"].as_array().unwrap().iter().collect();
+        assert_eq!(
+            (&posts[0]["post_id"], &posts[0]["kind"]),
+            (&"a".into(), &"like".into())
+        );
+        assert_eq!(posts[1]["post_id"], "b");
+        // Exactly `limit` entries were left, so there is no next page.
+        assert!(page.get("next_cursor").is_none());
+
+        let (_, page) = get_json(router, "/me/history?kind=like").await;
+        assert_eq!(page["entries"].as_array().unwrap().len(), 1);
+        assert!(page.get("next_cursor").is_none());
+    }
+
+    #[tokio::test]
+    async fn history_checks_the_limit_and_cursor() {
+        for uri in [
+            "/me/history?limit=0",
+            "/me/history?limit=101",
+            "/me/history?cursor=abc",
+        ] {
+            assert_eq!(
+                get_json(app(), uri).await.0,
+                StatusCode::BAD_REQUEST,
+                "{uri}"
+            );
+        }
+        for uri in ["/me/history?limit=1", "/me/history?limit=100"] {
+            assert_eq!(get_json(app(), uri).await.0, StatusCode::OK, "{uri}");
+        }
+    }
+
+    #[tokio::test]
+    async fn streams_the_summary_of_recorded_events() {
+        let mut config = Config::default();
+        config.ingest.flush_interval_ms = 10;
+        // Streams end once the app is gone, so keep it for the whole test.
+        let router = testing::app(Arc::default(), Vec::new(), &config).router;
+        let _app = router.clone();
+
+        assert_eq!(
+            get(router.clone(), "/events/1234567890/stream", None).await,
+            StatusCode::UNAUTHORIZED
+        );
+        assert_eq!(
+            get_json(router.clone(), "/events/short/stream").await.0,
+            StatusCode::BAD_REQUEST
+        );
+
+        let request = Request::get("/events/1234567890/stream")
+            .header("Authorization", "Bearer 1234567890")
+            .body(Body::empty())
+            .unwrap();
+        let response = router.clone().oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let mut body = response.into_body().into_data_stream();
+
+        let mut next_summary = async || {
+            let frame = tokio::time::timeout(std::time::Duration::from_secs(5), body.next())
+                .await
+                .unwrap()
+                .unwrap()
+                .unwrap();
+            let frame = String::from_utf8(frame.to_vec()).unwrap();
+            let data = frame
+                .lines()
+                .find_map(|line| line.strip_prefix("data: "))
+                .unwrap()
+                .to_owned();
+            serde_json::from_str::<serde_json::Value>(&data).unwrap()
+        };
+
+        assert_eq!(next_summary().await["views"], 0);
+        assert_eq!(post_event(router).await, StatusCode::CREATED);
+        assert_eq!(next_summary().await["views"], 1);
+    }
+
+    async fn export(
+        router: axum::Router,
+        uri: &str,
+        header: Option<(&str, &str)>,
+    ) -> (StatusCode, String) {
+        let mut request = Request::get(uri);
+        if let Some((name, value)) = header {
+            request = request.header(name, value);
+        }
+
+        let response = router
+            .oneshot(request.body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+        let status = response.status();
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        (status, String::from_utf8(body.to_vec()).unwrap())
+    }
+
+    async fn export_app() -> axum::Router {
+        let device = DeviceRequest {
+            os: "Linux".to_owned(),
+            browser: "Firefox".to_owned(),
+            screen_resolution: "1920x1080".to_owned(),
+            language: "es".to_owned(),
+        };
+        let someone_else = NewEvent {
+            user_id: Some("0987654321".to_owned()),
+            ..read("a", EventKind::View)
+        };
+
+        let repo = Arc::new(testing::MemoryRepository::default());
+        repo.create_events(&[
+            NewEvent {
+                device: Some(device),
+                referrer: Some("duckduckgo.com".to_owned()),
+                ..read("a", EventKind::View)
+            },
+            read("b", EventKind::Like),
+            someone_else,
+        ])
+        .await
+        .unwrap();
+
+        let keys = vec![
+            api_key("reader", vec![Scope::EventsRead]),
+            api_key("exporter", vec![Scope::UsersExport]),
+        ];
+        testing::app(repo, keys, &Config::default()).router
+    }
+
+    #[tokio::test]
+    async fn exports_the_summary_devices_and_events_as_json() {
+        let bearer = Some(("Authorization", "Bearer 1234567890"));
+        let (status, body) = export(export_app().await, "/me/export", bearer).await;
+        assert_eq!(status, StatusCode::OK);
+
+        let export: serde_json::Value = serde_json::from_str(&body).unwrap();
+        assert_eq!(export["user_id"], "1234567890");
+        assert_eq!(export["summary"]["views"], 1);
+        assert_eq!(export["summary"]["likes"], 1);
+        assert_eq!(export["devices"].as_array().unwrap().len(), 1);
+        assert_eq!(export["devices"][0]["os"], "Linux");
+
+        let events = export["events"].as_array().unwrap();
+        assert_eq!(events.len(), 2);
+        assert_eq!(events[0]["post_id"], "a");
+        assert_eq!(events[0]["device_id"], export["devices"][0]["id"]);
+        assert_eq!(events[0]["referrer"], "duckduckgo.com");
+        assert_eq!(events[1]["referrer"], serde_json::Value::Null);
+        assert_eq!(events[1]["kind"], "like");
+    }
+
+    #[tokio::test]
+    async fn exports_one_csv_row_per_event_with_its_device() {
+        let bearer = Some(("Authorization", "Bearer 1234567890"));
+        let (status, body) = export(export_app().await, "/me/export?format=csv", bearer).await;
+        assert_eq!(status, StatusCode::OK);
+
+        let lines: Vec<_> = body.lines().collect();
+        assert_eq!(
+            lines[0],
+            "id,post_id,kind,referrer,timestamp,os,browser,screen_resolution,language"
+        );
+        assert_eq!(lines.len(), 3);
+        assert!(lines[1].starts_with("1,a,view,duckduckgo.com,"));
+        assert!(lines[1].ends_with(",Linux,Firefox,1920x1080,es"));
+        assert!(lines[2].starts_with("2,b,like,,"));
+        assert!(lines[2].ends_with(",,,,"));
+    }
+
+    #[tokio::test]
+    async fn exports_need_the_user_or_a_key_with_their_scope() {
+        let router = export_app().await;
+        let status = |uri, header| {
+            let router = router.clone();
+            async move { export(router, uri, header).await.0 }
+        };
+
+        assert_eq!(status("/me/export", None).await, StatusCode::UNAUTHORIZED);
+
+        let uri = "/users/1234567890/export";
+        assert_eq!(status(uri, None).await, StatusCode::UNAUTHORIZED);
+        assert_eq!(
+            status(uri, Some(("Authorization", "Bearer 1234567890"))).await,
+            StatusCode::UNAUTHORIZED
+        );
+        assert_eq!(
+            status(uri, Some(("X-Internal-Token", "reader"))).await,
+            StatusCode::FORBIDDEN
+        );
+        assert_eq!(
+            status(uri, Some(("X-Internal-Token", "exporter"))).await,
+            StatusCode::OK
+        );
+    }
 }
@@ -1,10 +1,23 @@
 use std::sync::Arc;
 
-use crate::{db::EventRepository, posts::PostsApi, users::UsersApi};
+use crate::{
+    db::EventRepository,
+    domain::{ErasureMode, PurgeMode},
+    ingest::EventIngestor,
+    live::EventHub,
+    posts::PostsApi,
+    users::UsersApi,
+};
 
 #[derive(Clone)]
 pub struct AppState {
     pub repo: Arc<dyn EventRepository>,
     pub users: Arc<dyn UsersApi>,
     pub posts: Arc<dyn PostsApi>,
+    pub ingest: Arc<EventIngestor>,
+    pub live: Arc<EventHub>,
+    pub erasure_mode: ErasureMode,
+    pub purge_mode: PurgeMode,
+    /// Whether post metadata is synced, which lists the posts of an author.
+    pub catalog_enabled: bool,
 }
@@ -0,0 +1,339 @@
+use std::{
+    sync::{
+        Arc, Mutex,
+        atomic::{AtomicUsize, Ordering},
+    },
+    time::Duration,
+};
+
+use thiserror::Error;
+use tokio::{
+    sync::{Notify, mpsc, oneshot},
+    task::JoinHandle,
+};
+
+use crate::{
+    db::{EventRepository, NewEvent},
+    domain::EventKind,
+    http::ApiError,
+    live::EventHub,
+    metrics::METRICS,
+};
+
+#[derive(Debug, Clone)]
+pub struct IngestConfig {
+    /// How many events may wait to be written, queued or being retried,
+    /// before new ones are rejected.
+    pub capacity: usize,
+    /// How many events are written per insert.
+    pub batch_size: usize,
+    /// How long an incomplete batch may wait before being written.
+    pub flush_interval: Duration,
+}
+
+impl Default for IngestConfig {
+    fn default() -> Self {
+        Self {
+            capacity: 10_000,
+            batch_size: 500,
+            flush_interval: Duration::from_secs(1),
+        }
+    }
+}
+
+#[derive(Debug, Error)]
+pub enum IngestError {
+    #[error("Ingestion buffer is full")]
+    Full,
+
+    #[error("Ingestion is shutting down")]
+    Closed,
+}
+
+impl From<IngestError> for ApiError {
+    fn from(value: IngestError) -> Self {
+        Self::ServiceUnavailable(Some(value.to_string()))
+    }
+}
+
+/// Buffers incoming events and writes them in batches from a background task,
+/// then publishes the new summaries of the posts watched through `hub`.
+pub struct EventIngestor {
+    sender: mpsc::Sender<NewEvent>,
+    /// Events accepted but not yet written.
+    pending: Arc<AtomicUsize>,
+    capacity: usize,
+    flushes: mpsc::Sender<oneshot::Sender<bool>>,
+    shutdown: Arc<Notify>,
+    worker: Mutex<Option<JoinHandle<()>>>,
+}
+
+impl EventIngestor {
+    pub fn spawn(repo: Arc<dyn EventRepository>, config: IngestConfig, hub: Arc<EventHub>) -> Self {
+        let (sender, receiver) = mpsc::channel(config.capacity);
+        let (flushes, flush_requests) = mpsc::channel(16);
+        let pending = Arc::new(AtomicUsize::new(0));
+        let shutdown = Arc::new(Notify::new());
+        let capacity = config.capacity;
+
+        let worker = tokio::spawn(run(
+            repo,
+            receiver,
+            flush_requests,
+            config,
+            hub,
+            pending.clone(),
+            shutdown.clone(),
+        ));
+
+        Self {
+            sender,
+            pending,
+            capacity,
+            flushes,
+            shutdown,
+            worker: Mutex::new(Some(worker)),
+        }
+    }
+
+    /// Queues an event, failing right away if the buffer is full.
+    pub fn push(&self, event: NewEvent) -> Result<(), IngestError> {
+        self.pending
+            .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |pending| {
+                (pending < self.capacity).then_some(pending + 1)
+            })
+            .map_err(|_| IngestError::Full)?;
+
+        self.sender.try_send(event).map_err(|e| {
+            self.pending.fetch_sub(1, Ordering::SeqCst);
+            match e {
+                mpsc::error::TrySendError::Full(_) => IngestError::Full,
+                mpsc::error::TrySendError::Closed(_) => IngestError::Closed,
+            }
+        })
+    }
+
+    /// Writes the events queued so far, returning whether they all were.
+    pub async fn flush(&self) -> bool {
+        let (done, flushed) = oneshot::channel();
+        if self.flushes.send(done).await.is_err() {
+            return false;
+        }
+        flushed.await.unwrap_or(false)
+    }
+
+    /// Stops accepting events and waits for the buffered ones to be written.
+    pub async fn shutdown(&self) {
+        self.shutdown.notify_one();
+
+        let worker = self.worker.lock().unwrap().take();
+        if let Some(worker) = worker
+            && let Err(e) = worker.await
+        {
+            tracing::error!("Event ingestion worker failed: {e:?}");
+        }
+    }
+}
+
+async fn run(
+    repo: Arc<dyn EventRepository>,
+    mut receiver: mpsc::Receiver<NewEvent>,
+    mut flush_requests: mpsc::Receiver<oneshot::Sender<bool>>,
+    config: IngestConfig,
+    hub: Arc<EventHub>,
+    pending: Arc<AtomicUsize>,
+    shutdown: Arc<Notify>,
+) {
+    let mut batch = Vec::with_capacity(config.batch_size);
+    let mut interval = tokio::time::interval(config.flush_interval);
+    // After a failed write, wait for the next tick instead of retrying on
+    // every new event.
+    let mut failing = false;
+
+    let flush_pending = async |batch: &mut Vec<NewEvent>| {
+        let before = batch.len();
+        let written = flush(repo.as_ref(), &hub, batch, config.batch_size).await;
+        // Events that failed to be written still count towards the capacity,
+        // so new ones are rejected while the database is unavailable.
+        pending.fetch_sub(before - batch.len(), Ordering::SeqCst);
+        written
+    };
+
+    loop {
+        tokio::select! {
+            event = receiver.recv() => match event {
+                Some(event) => {
+                    batch.push(event);
+                    if !failing && batch.len() >= config.batch_size {
+                        failing = !flush_pending(&mut batch).await;
+                    }
+                }
+                None => break,
+            },
+            _ = interval.tick() => {
+                failing = !flush_pending(&mut batch).await;
+            }
+            Some(done) = flush_requests.recv() => {
+                while let Ok(event) = receiver.try_recv() {
+                    batch.push(event);
+                }
+                failing = !flush_pending(&mut batch).await;
+                done.send(!failing).ok();
+            }
+            // Closing the channel lets `recv` drain what is left and then stop.
+            _ = shutdown.notified() => {
+                receiver.close();
+            }
+        }
+    }
+
+    if !flush_pending(&mut batch).await {
+        tracing::error!("Dropping {} events on shutdown", batch.len());
+    }
+}
+
+/// Writes the events in `batch` in inserts of up to `batch_size`, removing the
+/// written ones. Returns whether every event was written; the rest are kept
+/// to be retried.
+async fn flush(
+    repo: &dyn EventRepository,
+    hub: &EventHub,
+    batch: &mut Vec<NewEvent>,
+    batch_size: usize,
+) -> bool {
+    const ATTEMPTS: u32 = 3;
+
+    while !batch.is_empty() {
+        let chunk = &batch[..batch.len().min(batch_size)];
+
+        let mut attempt = 1;
+        while let Err(e) = repo.create_events(chunk).await {
+            if attempt == ATTEMPTS {
+                tracing::error!(
+                    "Failed to write {} events, keeping them for later: {e:?}",
+                    batch.len()
+                );
+                return false;
+            }
+
+            tracing::warn!("Failed to write {} events, retrying: {e:?}", chunk.len());
+            tokio::time::sleep(Duration::from_millis(100 * attempt as u64)).await;
+            attempt += 1;
+        }
+
+        for kind in [EventKind::View, EventKind::Like, EventKind::Share] {
+            let count = chunk.iter().filter(|e| e.kind == kind).count();
+            METRICS.record_ingested(kind, count as u64);
+        }
+
+        publish_summaries(repo, hub, chunk).await;
+
+        let written = chunk.len();
+        batch.drain(..written);
+    }
+
+    true
+}
+
+/// Sends the new summaries of the posts in `batch` that are watched live.
+async fn publish_summaries(repo: &dyn EventRepository, hub: &EventHub, batch: &[NewEvent]) {
+    let mut post_ids: Vec<&str> = batch.iter().map(|e| e.post_id.as_str()).collect();
+    post_ids.sort_unstable();
+    post_ids.dedup();
+
+    let watched = hub.watched(post_ids);
+    if watched.is_empty() {
+        return;
+    }
+
+    match repo.find_event_summaries(&watched).await {
+        Ok(summaries) => {
+            for (post_id, summary) in watched.into_iter().zip(summaries) {
+                hub.publish(post_id, summary);
+            }
+        }
+        Err(e) => tracing::warn!("Failed to read the summaries of watched posts: {e:?}"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::Utc;
+
+    use super::*;
+    use crate::testing::MemoryRepository;
+
+    fn event() -> NewEvent {
+        NewEvent {
+            post_id: "1234567890".to_owned(),
+            kind: EventKind::View,
+            device: None,
+            user_id: None,
+            referrer: None,
+            timestamp: Utc::now(),
+        }
+    }
+
+    fn ingestor(repo: &Arc<MemoryRepository>, capacity: usize, batch_size: usize) -> EventIngestor {
+        let config = IngestConfig {
+            capacity,
+            batch_size,
+            flush_interval: Duration::from_secs(60 * 60),
+        };
+        EventIngestor::spawn(repo.clone(), config, Arc::default())
+    }
+
+    #[tokio::test]
+    async fn writes_full_batches_and_the_rest_on_shutdown() {
+        let repo = Arc::new(MemoryRepository::default());
+        let ingestor = ingestor(&repo, 10, 2);
+
+        for _ in 0..5 {
+            ingestor.push(event()).unwrap();
+        }
+        ingestor.shutdown().await;
+
+        assert_eq!(*repo.batches.lock().unwrap(), [2, 2, 1]);
+        assert!(matches!(ingestor.push(event()), Err(IngestError::Closed)));
+    }
+
+    #[tokio::test]
+    async fn flushes_on_request() {
+        let repo = Arc::new(MemoryRepository::default());
+        let ingestor = ingestor(&repo, 10, 5);
+
+        ingestor.push(event()).unwrap();
+        ingestor.push(event()).unwrap();
+        assert!(ingestor.flush().await);
+        assert_eq!(repo.event_count(), 2);
+
+        repo.fail_writes(u32::MAX);
+        ingestor.push(event()).unwrap();
+        assert!(!ingestor.flush().await);
+    }
+
+    #[tokio::test]
+    async fn keeps_failed_batches_for_later() {
+        let repo = Arc::new(MemoryRepository::default());
+        // Every attempt of the first flush fails.
+        repo.fail_writes(3);
+        let ingestor = ingestor(&repo, 10, 2);
+
+        ingestor.push(event()).unwrap();
+        ingestor.push(event()).unwrap();
+        ingestor.shutdown().await;
+
+        assert_eq!(repo.event_count(), 2);
+    }
+
+    #[tokio::test]
+    async fn rejects_events_while_writes_fail() {
+        let repo = Arc::new(MemoryRepository::default());
+        repo.fail_writes(u32::MAX);
+        let ingestor = ingestor(&repo, 2, 1);
+
+        // Queued events and those held for retrying share the capacity.
+        let accepted = (0..10).filter(|_| ingestor.push(event()).is_ok()).count();
+        assert_eq!(accepted, 2);
+    }
+}
@@ -0,0 +1,216 @@
+use std::{collections::HashMap, fs, path::Path};
+
+use anyhow::{Context, anyhow};
+use async_trait::async_trait;
+use jsonwebtoken::{
+    Algorithm, DecodingKey, Validation, decode, decode_header, errors::ErrorKind, jwk::JwkSet,
+};
+use serde_json::Value;
+
+use crate::users::{FetchUserError, User, UsersApi, bearer_token};
+
+/// Authenticates users by verifying the JWTs issued by the users service
+/// locally, instead of calling `/introspect` on every request.
+#[derive(Clone)]
+pub struct JwtUsersClient {
+    keys: JwtKeys,
+    validation: Validation,
+    user_id_claim: String,
+}
+
+#[derive(Clone)]
+enum JwtKeys {
+    Secret(DecodingKey),
+    Jwks(JwkSet),
+}
+
+impl JwtUsersClient {
+    /// Verifies HS256 tokens signed with a shared secret.
+    pub fn with_secret(secret: &str) -> Self {
+        Self::new(
+            JwtKeys::Secret(DecodingKey::from_secret(secret.as_bytes())),
+            Algorithm::HS256,
+        )
+    }
+
+    /// Verifies RS256 tokens against the keys in a JWKS file.
+    pub fn from_jwks_file(path: impl AsRef<Path>) -> anyhow::Result<Self> {
+        let path = path.as_ref();
+        let contents =
+            fs::read_to_string(path).with_context(|| format!("reading {}", path.display()))?;
+        let jwks: JwkSet = serde_json::from_str(&contents)
+            .with_context(|| format!("parsing JWKS from {}", path.display()))?;
+
+        Ok(Self::new(JwtKeys::Jwks(jwks), Algorithm::RS256))
+    }
+
+    fn new(keys: JwtKeys, algorithm: Algorithm) -> Self {
+        let mut validation = Validation::new(algorithm);
+        validation.validate_nbf = true;
+        validation.validate_aud = false;
+
+        Self {
+            keys,
+            validation,
+            user_id_claim: "sub".to_owned(),
+        }
+    }
+
+    /// Requires the `aud` claim to contain one of the given audiences.
+    pub fn with_audience<T: ToString>(mut self, audience: &[T]) -> Self {
+        self.validation.set_audience(audience);
+        self.validation.validate_aud = true;
+        self
+    }
+
+    /// Reads the user ID from `claim` instead of `sub`.
+    pub fn with_user_id_claim(mut self, claim: impl Into<String>) -> Self {
+        self.user_id_claim = claim.into();
+        self
+    }
+
+    fn decoding_key(&self, token: &str) -> Result<DecodingKey, FetchUserError> {
+        match &self.keys {
+            JwtKeys::Secret(key) => Ok(key.clone()),
+            JwtKeys::Jwks(jwks) => {
+                let header =
+                    decode_header(token).map_err(|_| FetchUserError::InvalidAuthorization)?;
+
+                // Without a `kid`, which key signed the token is only known
+                // when there is a single one.
+                let jwk = match (header.kid, jwks.keys.as_slice()) {
+                    (Some(kid), _) => jwks.find(&kid),
+                    (None, [key]) => Some(key),
+                    (None, _) => None,
+                }
+                .ok_or(FetchUserError::InvalidAuthorization)?;
+
+                DecodingKey::from_jwk(jwk).map_err(|e| anyhow!(e).into())
+            }
+        }
+    }
+}
+
+#[async_trait]
+impl UsersApi for JwtUsersClient {
+    async fn fetch_user(&self, authorization: &str) -> Result<Option<User>, FetchUserError> {
+        let token = bearer_token(authorization)?;
+        let key = self.decoding_key(token)?;
+
+        let data =
+            decode::<HashMap<String, Value>>(token, &key, &self.validation).map_err(|e| match e
+                .kind()
+            {
+                ErrorKind::InvalidKeyFormat | ErrorKind::Crypto(_) => anyhow!(e).into(),
+                _ => FetchUserError::InvalidAuthorization,
+            })?;
+
+        let id = match data.claims.get(&self.user_id_claim) {
+            Some(Value::String(id)) => id.clone(),
+            Some(Value::Number(id)) => id.to_string(),
+            _ => return Ok(None),
+        };
+
+        Ok(Some(User { id }))
+    }
+
+    async fn ping(&self) -> anyhow::Result<()> {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use jsonwebtoken::{EncodingKey, Header, encode};
+    use serde_json::json;
+
+    use super::*;
+
+    const SECRET: &str = "test-secret";
+
+    fn sign(claims: Value) -> String {
+        encode(
+            &Header::default(),
+            &claims,
+            &EncodingKey::from_secret(SECRET.as_bytes()),
+        )
+        .unwrap()
+    }
+
+    fn in_an_hour() -> i64 {
+        chrono::Utc::now().timestamp() + 3600
+    }
+
+    #[tokio::test]
+    async fn accepts_valid_token() {
+        let client = JwtUsersClient::with_secret(SECRET);
+        let token = sign(json!({ "sub": "42", "exp": in_an_hour() }));
+
+        let user = client.fetch_user(&format!("Bearer {token}")).await.unwrap();
+        assert_eq!(user.unwrap().id, "42");
+    }
+
+    #[tokio::test]
+    async fn rejects_expired_or_foreign_tokens() {
+        let client = JwtUsersClient::with_secret(SECRET);
+
+        let expired = sign(json!({ "sub": "42", "exp": 1000 }));
+        assert!(matches!(
+            client.fetch_user(&expired).await,
+            Err(FetchUserError::InvalidAuthorization)
+        ));
+
+        let foreign = JwtUsersClient::with_secret("other-secret");
+        let token = sign(json!({ "sub": "42", "exp": in_an_hour() }));
+        assert!(matches!(
+            foreign.fetch_user(&token).await,
+            Err(FetchUserError::InvalidAuthorization)
+        ));
+    }
+
+    #[test]
+    fn needs_a_kid_to_choose_between_keys() {
+        let jwk = |kid: &str| json!({ "kty": "RSA", "kid": kid, "n": "AQAB", "e": "AQAB" });
+        let client = |keys: Vec<Value>| {
+            let jwks = serde_json::from_value(json!({ "keys": keys })).unwrap();
+            JwtUsersClient::new(JwtKeys::Jwks(jwks), Algorithm::RS256)
+        };
+        let token = |kid: Option<&str>| {
+            let header = Header {
+                kid: kid.map(str::to_owned),
+                ..Default::default()
+            };
+            encode(&header, &json!({}), &EncodingKey::from_secret(b"")).unwrap()
+        };
+
+        let single = client(vec![jwk("a")]);
+        assert!(single.decoding_key(&token(None)).is_ok());
+
+        let rotated = client(vec![jwk("a"), jwk("b")]);
+        assert!(rotated.decoding_key(&token(Some("b"))).is_ok());
+        assert!(matches!(
+            rotated.decoding_key(&token(None)),
+            Err(FetchUserError::InvalidAuthorization)
+        ));
+        assert!(matches!(
+            rotated.decoding_key(&token(Some("c"))),
+            Err(FetchUserError::InvalidAuthorization)
+        ));
+    }
+
+    #[tokio::test]
+    async fn checks_audience_and_custom_claim() {
+        let client = JwtUsersClient::with_secret(SECRET)
+            .with_audience(&["mittel"])
+            .with_user_id_claim("user_id");
+
+        let token = sign(json!({ "user_id": 7, "aud": "mittel", "exp": in_an_hour() }));
+        assert_eq!(client.fetch_user(&token).await.unwrap().unwrap().id, "7");
+
+        let token = sign(json!({ "user_id": 7, "aud": "elsewhere", "exp": in_an_hour() }));
+        assert!(client.fetch_user(&token).await.is_err());
+
+        let token = sign(json!({ "sub": "7", "aud": "mittel", "exp": in_an_hour() }));
+        assert!(client.fetch_user(&token).await.unwrap().is_none());
+    }
+}
@@ -0,0 +1,182 @@
+use std::{collections::HashMap, sync::Mutex};
+
+use futures_util::Stream;
+use tokio::sync::{
+    broadcast::{self, error::RecvError},
+    watch,
+};
+
+use crate::domain::EventSummary;
+
+/// How many updates a slow subscriber may fall behind before skipping some.
+const CHANNEL_CAPACITY: usize = 16;
+
+/// How many streams may be open at once by default.
+const MAX_SUBSCRIPTIONS: usize = 10_000;
+
+/// Fans out the summaries of posts to the clients watching them live.
+pub struct EventHub {
+    channels: Mutex<HashMap<String, broadcast::Sender<EventSummary>>>,
+    closed: watch::Sender<bool>,
+    max_subscriptions: usize,
+}
+
+impl Default for EventHub {
+    fn default() -> Self {
+        Self::new(MAX_SUBSCRIPTIONS)
+    }
+}
+
+impl EventHub {
+    pub fn new(max_subscriptions: usize) -> Self {
+        Self {
+            channels: Mutex::new(HashMap::new()),
+            closed: watch::Sender::new(false),
+            max_subscriptions,
+        }
+    }
+
+    /// Starts receiving the summaries published for a post, or returns `None`
+    /// if `max_subscriptions` streams are already open.
+    pub fn subscribe(&self, post_id: &str) -> Option<Subscription> {
+        let mut channels = self.channels.lock().unwrap();
+        // Forget the posts nobody watches anymore.
+        channels.retain(|_, sender| sender.receiver_count() > 0);
+
+        let open: usize = channels.values().map(|s| s.receiver_count()).sum();
+        if open >= self.max_subscriptions {
+            return None;
+        }
+
+        let receiver = match channels.get(post_id) {
+            Some(sender) => sender.subscribe(),
+            None => {
+                let (sender, receiver) = broadcast::channel(CHANNEL_CAPACITY);
+                channels.insert(post_id.to_owned(), sender);
+                receiver
+            }
+        };
+
+        Some(Subscription {
+            receiver,
+            closed: self.closed.subscribe(),
+        })
+    }
+
+    /// Returns which of the given posts someone is watching.
+    pub fn watched<'a>(&self, post_ids: impl IntoIterator<Item = &'a str>) -> Vec<&'a str> {
+        let channels = self.channels.lock().unwrap();
+        post_ids
+            .into_iter()
+            .filter(|post_id| {
+                channels
+                    .get(*post_id)
+                    .is_some_and(|sender| sender.receiver_count() > 0)
+            })
+            .collect()
+    }
+
+    pub fn publish(&self, post_id: &str, summary: EventSummary) {
+        let mut channels = self.channels.lock().unwrap();
+        if let Some(sender) = channels.get(post_id)
+            && sender.send(summary).is_err()
+        {
+            channels.remove(post_id);
+        }
+    }
+
+    /// Ends every subscription, so that open streams don't hold up shutdown.
+    pub fn close(&self) {
+        self.closed.send_replace(true);
+    }
+}
+
+pub struct Subscription {
+    receiver: broadcast::Receiver<EventSummary>,
+    closed: watch::Receiver<bool>,
+}
+
+impl Subscription {
+    /// Yields each published summary until the hub is closed.
+    pub fn into_stream(mut self) -> impl Stream<Item = EventSummary> {
+        async_stream::stream! {
+            loop {
+                tokio::select! {
+                    update = self.receiver.recv() => match update {
+                        Ok(summary) => yield summary,
+                        // Every update is a full summary, so skipped ones are not missed.
+                        Err(RecvError::Lagged(_)) => continue,
+                        Err(RecvError::Closed) => break,
+                    },
+                    _ = closed(&mut self.closed) => break,
+                }
+            }
+        }
+    }
+}
+
+async fn closed(closed: &mut watch::Receiver<bool>) {
+    // Also done if the hub is gone. The guard is dropped right away, as it is
+    // not `Send`.
+    let _ = closed.wait_for(|closed| *closed).await;
+}
+
+#[cfg(test)]
+mod tests {
+    use futures_util::StreamExt;
+
+    use super::*;
+
+    fn summary(views: usize) -> EventSummary {
+        EventSummary {
+            views,
+            ..Default::default()
+        }
+    }
+
+    #[tokio::test]
+    async fn delivers_summaries_of_the_watched_post() {
+        let hub = EventHub::default();
+        let stream = hub.subscribe("a").unwrap().into_stream();
+        tokio::pin!(stream);
+
+        assert_eq!(hub.watched(["a", "b"]), ["a"]);
+
+        hub.publish("b", summary(1));
+        hub.publish("a", summary(2));
+        assert_eq!(stream.next().await.unwrap().views, 2);
+    }
+
+    #[tokio::test]
+    async fn closing_ends_the_streams() {
+        let hub = EventHub::default();
+        let stream = hub.subscribe("a").unwrap().into_stream();
+        tokio::pin!(stream);
+
+        hub.close();
+        assert!(stream.next().await.is_none());
+    }
+
+    #[test]
+    fn forgets_posts_nobody_watches() {
+        let hub = EventHub::default();
+        drop(hub.subscribe("a"));
+
+        assert!(hub.watched(["a"]).is_empty());
+        hub.publish("a", summary(1));
+        assert!(hub.channels.lock().unwrap().is_empty());
+    }
+
+    #[test]
+    fn caps_open_subscriptions() {
+        let hub = EventHub::new(2);
+        let a = hub.subscribe("a").unwrap();
+        let _b = hub.subscribe("b").unwrap();
+
+        assert!(hub.subscribe("a").is_none());
+        assert!(hub.subscribe("c").is_none());
+
+        drop(a);
+        assert!(hub.subscribe("c").is_some());
+    }
+}
@@ -1,49 +1,276 @@
+pub mod catalog;
+pub mod cli;
+pub mod config;
 pub mod db;
 pub mod domain;
 pub mod http;
+pub mod ingest;
+pub mod jwt;
+pub mod live;
+pub mod metrics;
 pub mod posts;
+pub mod recommendations;
+pub mod retention;
+pub mod telemetry;
+#[cfg(test)]
+pub mod testing;
 pub mod users;
 
-use env_logger::Env;
-use std::{env, sync::Arc};
-use tokio::net::TcpListener;
+use clap::Parser;
+use std::{sync::Arc, time::Duration};
+use tokio::{
+    net::TcpListener,
+    sync::oneshot,
+    time::{Instant, timeout_at},
+};
 
 use crate::{
-    db::MySql, http::state::AppState, posts::PostsMicroserviceClient,
-    users::UsersMicroserviceClient,
+    cli::{Cli, Command},
+    config::{AuthConfig, Config, UsersAuth, UsersConfig},
+    db::{EventRepository, MigrationReport, MySql},
+    http::{auth::ApiKeyStore, state::AppState},
+    ingest::EventIngestor,
+    jwt::JwtUsersClient,
+    live::EventHub,
+    posts::{PostsApi, PostsMicroserviceClient},
+    users::{UsersApi, UsersMicroserviceClient},
 };
 
 #[tokio::main]
 async fn main() -> anyhow::Result<()> {
-    env_logger::Builder::from_env(Env::default().default_filter_or("info")).init();
+    let cli = Cli::parse();
+    let command = cli.command.unwrap_or(Command::Serve);
+
+    let telemetry = telemetry::init(command.log_output())?;
+    let config = Config::load(&cli.config, command.config_usage())?;
+
+    let (result, deadline) = match command {
+        Command::Serve => match serve_api(&config).await {
+            Ok(deadline) => (Ok(()), Some(deadline)),
+            Err(e) => (Err(e), None),
+        },
+        Command::CheckConfig => {
+            println!("Configuration is valid");
+            (Ok(()), None)
+        }
+        command => (cli::run(command, &config).await, None),
+    };
+
+    telemetry.shutdown(deadline).await;
+    result
+}
+
+/// Serves the API until stopped, returning by when the rest of the shutdown
+/// must be done.
+async fn serve_api(config: &Config) -> anyhow::Result<Instant> {
+    let api_keys = api_keys(&config.auth)?;
+    let mysql = Arc::new(MySql::connect(&config.database).await?);
 
-    let database_url = env::var("DATABASE_URL").expect("DATABASE_URL not found");
-    let mysql = MySql::new(&database_url).await?;
+    if config.database.auto_migrate {
+        mysql.migrate(config.migration_lock_timeout()).await?;
+    } else {
+        let pending = MigrationReport::from(mysql.migration_status().await?).pending;
+        if pending > 0 {
+            tracing::warn!("{pending} pending migrations, run `migrate up` to apply them");
+        }
+    }
 
-    let users_url = env::var("USERS_URL").expect("USERS_URL not found");
-    let articles_url = env::var("ARTICLES_URL").expect("ARTICLES_URL not found");
+    let retention_policy = config.retention_policy();
+    let retention = (config.features.retention && !retention_policy.keeps_everything())
+        .then(|| retention::spawn(mysql.clone(), retention_policy));
 
-    let users_client = UsersMicroserviceClient::new(&users_url);
-    let posts_client = PostsMicroserviceClient::new(&articles_url);
+    let users_client = users_client(&config.users)?;
+    let posts_client = PostsMicroserviceClient::new(&config.articles.url)
+        .with_timeout(Duration::from_secs(config.articles.timeout_secs))
+        .with_cache(
+            Duration::from_secs(config.articles.cache_ttl_secs),
+            config.articles.cache_capacity,
+        );
+
+    let posts_client: Arc<dyn PostsApi> = Arc::new(posts_client);
+
+    let catalog = config
+        .features
+        .catalog
+        .then(|| catalog::spawn(mysql.clone(), posts_client.clone(), config.catalog_policy()));
+
+    let recommendations = config
+        .features
+        .recommendations
+        .then(|| recommendations::spawn(mysql.clone(), config.recommendation_policy()));
+
+    let live = Arc::new(EventHub::default());
+    let ingest = Arc::new(EventIngestor::spawn(
+        mysql.clone(),
+        config.ingest_config(),
+        live.clone(),
+    ));
 
     let state = Arc::new(AppState {
-        repo: Arc::new(mysql),
-        users: Arc::new(users_client),
-        posts: Arc::new(posts_client),
+        repo: mysql.clone(),
+        users: users_client,
+        posts: posts_client,
+        ingest: ingest.clone(),
+        live: live.clone(),
+        erasure_mode: config.privacy.erasure_mode,
+        purge_mode: config.privacy.post_purge_mode,
+        catalog_enabled: config.features.catalog,
+    });
+
+    let app = crate::http::app(api_keys, config).with_state(state);
+
+    let listener = TcpListener::bind(config.addr()).await?;
+
+    tracing::info!("Listening at {}", listener.local_addr()?);
+    let deadline = serve(listener, app, config.shutdown_timeout(), || live.close()).await?;
+
+    if let Some(retention) = retention {
+        retention.abort();
+    }
+    if let Some(catalog) = catalog {
+        catalog.abort();
+    }
+    if let Some(recommendations) = recommendations {
+        recommendations.abort();
+    }
+
+    tracing::info!("Flushing buffered events");
+    if timeout_at(deadline, ingest.shutdown()).await.is_err() {
+        tracing::error!("Buffered events still unwritten at the shutdown deadline, dropping them");
+    }
+
+    if timeout_at(deadline, mysql.close()).await.is_err() {
+        tracing::warn!("Database connections still busy at the shutdown deadline, closing anyway");
+    }
+    tracing::info!("Shut down");
+
+    Ok(deadline)
+}
+
+/// Serves `app` until SIGTERM or SIGINT, then calls `on_stop` and waits for
+/// in-flight requests to finish. The whole shutdown gets `timeout`, so the
+/// deadline is returned for the steps that follow.
+async fn serve(
+    listener: TcpListener,
+    app: axum::Router,
+    timeout: Duration,
+    on_stop: impl FnOnce(),
+) -> anyhow::Result<Instant> {
+    let (stop, stopped) = oneshot::channel::<()>();
+
+    let mut server = tokio::spawn(async move {
+        axum::serve(listener, app)
+            .with_graceful_shutdown(async move {
+                stopped.await.ok();
+            })
+            .await
     });
 
-    let secret_token =
-        std::env::var("INTERNAL_SECRET_TOKEN").expect("INTERNAL_SECRET_TOKEN not found");
-    let app = crate::http::app(&secret_token).with_state(state);
+    tokio::select! {
+        result = &mut server => {
+            result??;
+            return Ok(Instant::now() + timeout);
+        }
+        _ = shutdown_signal() => {}
+    }
+
+    let deadline = Instant::now() + timeout;
+
+    tracing::info!("Shutting down, draining in-flight requests");
+    stop.send(()).ok();
+    on_stop();
+
+    match timeout_at(deadline, &mut server).await {
+        Ok(result) => result??,
+        Err(_) => {
+            tracing::warn!("Requests still in flight after {timeout:?}, dropping them");
+            server.abort();
+        }
+    }
+
+    Ok(deadline)
+}
+
+async fn shutdown_signal() {
+    let interrupt = async {
+        tokio::signal::ctrl_c().await.ok();
+    };
+
+    #[cfg(unix)]
+    let terminate = async {
+        use tokio::signal::unix::{SignalKind, signal};
+
+        match signal(SignalKind::terminate()) {
+            Ok(mut terminate) => {
+                terminate.recv().await;
+            }
+            Err(_) => std::future::pending().await,
+        }
+    };
+
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
+
+    tokio::select! {
+        _ = interrupt => {}
+        _ = terminate => {}
+    }
+}
+
+fn users_client(config: &UsersConfig) -> anyhow::Result<Arc<dyn UsersApi>> {
+    match config.auth {
+        UsersAuth::Introspect => {
+            // Presence was checked when loading the configuration.
+            let url = config.url.as_deref().unwrap_or_default();
+            let client = UsersMicroserviceClient::new(url)
+                .with_timeout(Duration::from_secs(config.timeout_secs));
+            Ok(Arc::new(client))
+        }
+        UsersAuth::Jwt => {
+            let mut client = match (&config.jwt_jwks_file, &config.jwt_secret) {
+                (Some(path), _) => JwtUsersClient::from_jwks_file(path)?,
+                (None, Some(secret)) => JwtUsersClient::with_secret(secret),
+                (None, None) => anyhow::bail!("JWT_SECRET or JWT_JWKS_FILE not found"),
+            };
+
+            if !config.jwt_audience.is_empty() {
+                client = client.with_audience(&config.jwt_audience);
+            }
+
+            if let Some(claim) = &config.jwt_user_id_claim {
+                client = client.with_user_id_claim(claim);
+            }
+
+            Ok(Arc::new(client))
+        }
+    }
+}
+
+fn api_keys(config: &AuthConfig) -> anyhow::Result<Arc<ApiKeyStore>> {
+    let keys = Arc::new(ApiKeyStore::new(
+        config.api_keys_file.clone(),
+        config.internal_tokens.clone(),
+    )?);
+
+    #[cfg(unix)]
+    tokio::spawn(reload_api_keys_on_sighup(keys.clone()));
+
+    Ok(keys)
+}
 
-    let host = env::var("HOST").unwrap_or("0.0.0.0".to_owned());
-    let port = env::var("PORT").unwrap_or("8080".to_owned());
-    let addr = format!("{host}:{port}");
+/// Only the keys file is re-read; `INTERNAL_SECRET_TOKEN` is fixed at startup.
+#[cfg(unix)]
+async fn reload_api_keys_on_sighup(keys: Arc<ApiKeyStore>) -> anyhow::Result<()> {
+    use tokio::signal::unix::{SignalKind, signal};
 
-    let listener = TcpListener::bind(addr).await?;
+    let mut hangup = signal(SignalKind::hangup())?;
 
-    log::info!("Listening at {}", listener.local_addr()?);
-    axum::serve(listener, app).await?;
+    while hangup.recv().await.is_some() {
+        match keys.reload() {
+            Ok(count) => tracing::info!("Reloaded {count} API keys"),
+            Err(e) => tracing::error!("Failed to reload API keys, keeping current ones: {e:?}"),
+        }
+    }
 
     Ok(())
 }
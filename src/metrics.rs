@@ -0,0 +1,151 @@
+use std::{sync::LazyLock, time::Instant};
+
+use prometheus::{
+    Encoder, HistogramOpts, HistogramVec, IntCounterVec, IntGaugeVec, Opts, Registry, TextEncoder,
+};
+
+use crate::{db::PoolStats, domain::EventKind, http::request_id, telemetry};
+
+pub struct Metrics {
+    registry: Registry,
+    pub http_requests: IntCounterVec,
+    pub http_request_duration: HistogramVec,
+    pub events_ingested: IntCounterVec,
+    pub upstream_request_duration: HistogramVec,
+    pub upstream_errors: IntCounterVec,
+    pub db_connections: IntGaugeVec,
+}
+
+pub static METRICS: LazyLock<Metrics> = LazyLock::new(Metrics::new);
+
+impl Metrics {
+    fn new() -> Self {
+        let registry = Registry::new_custom(Some("engagement".to_owned()), None).unwrap();
+
+        let http_requests = IntCounterVec::new(
+            Opts::new("http_requests_total", "HTTP requests handled"),
+            &["method", "route", "status"],
+        )
+        .unwrap();
+
+        let http_request_duration = HistogramVec::new(
+            HistogramOpts::new(
+                "http_request_duration_seconds",
+                "Time spent handling HTTP requests",
+            ),
+            &["method", "route", "status"],
+        )
+        .unwrap();
+
+        let events_ingested = IntCounterVec::new(
+            Opts::new("events_ingested_total", "Events written to the database"),
+            &["kind"],
+        )
+        .unwrap();
+
+        let upstream_request_duration = HistogramVec::new(
+            HistogramOpts::new(
+                "upstream_request_duration_seconds",
+                "Time spent on requests to the users and articles services",
+            ),
+            &["service", "operation"],
+        )
+        .unwrap();
+
+        let upstream_errors = IntCounterVec::new(
+            Opts::new(
+                "upstream_errors_total",
+                "Failed requests to the users and articles services",
+            ),
+            &["service", "operation"],
+        )
+        .unwrap();
+
+        let db_connections = IntGaugeVec::new(
+            Opts::new("db_connections", "Database pool connections"),
+            &["state"],
+        )
+        .unwrap();
+
+        registry.register(Box::new(http_requests.clone())).unwrap();
+        registry
+            .register(Box::new(http_request_duration.clone()))
+            .unwrap();
+        registry
+            .register(Box::new(events_ingested.clone()))
+            .unwrap();
+        registry
+            .register(Box::new(upstream_request_duration.clone()))
+            .unwrap();
+        registry
+            .register(Box::new(upstream_errors.clone()))
+            .unwrap();
+        registry.register(Box::new(db_connections.clone())).unwrap();
+
+        Self {
+            registry,
+            http_requests,
+            http_request_duration,
+            events_ingested,
+            upstream_request_duration,
+            upstream_errors,
+            db_connections,
+        }
+    }
+
+    pub fn record_ingested(&self, kind: EventKind, count: u64) {
+        let kind = match kind {
+            EventKind::View => "view",
+            EventKind::Like => "like",
+            EventKind::Share => "share",
+        };
+        self.events_ingested
+            .with_label_values(&[kind])
+            .inc_by(count);
+    }
+
+    pub fn render(&self, pool: PoolStats) -> String {
+        self.db_connections
+            .with_label_values(&["max"])
+            .set(pool.max.into());
+        self.db_connections
+            .with_label_values(&["open"])
+            .set(pool.size.into());
+        self.db_connections
+            .with_label_values(&["idle"])
+            .set(pool.idle as i64);
+
+        let mut buffer = Vec::new();
+        TextEncoder::new()
+            .encode(&self.registry.gather(), &mut buffer)
+            .unwrap();
+        String::from_utf8(buffer).unwrap()
+    }
+}
+
+/// Sends a request to an upstream service, recording its latency and errors.
+#[tracing::instrument(skip(request), fields(otel.kind = "client"))]
+pub async fn track_upstream(
+    service: &str,
+    operation: &str,
+    request: reqwest::RequestBuilder,
+) -> reqwest::Result<reqwest::Response> {
+    let start = Instant::now();
+    let res = telemetry::inject_context(request_id::forward(request))
+        .send()
+        .await;
+
+    METRICS
+        .upstream_request_duration
+        .with_label_values(&[service, operation])
+        .observe(start.elapsed().as_secs_f64());
+
+    if res.as_ref().map_or(true, |r| r.status().is_server_error()) {
+        METRICS
+            .upstream_errors
+            .with_label_values(&[service, operation])
+            .inc();
+    }
+
+    res
+}
@@ -1,16 +1,89 @@
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
 use anyhow::anyhow;
 use async_trait::async_trait;
+use chrono::{DateTime, Utc};
 use reqwest::{Client, IntoUrl, StatusCode, Url};
+use serde::Deserialize;
+
+use crate::metrics;
 
 #[async_trait]
 pub trait PostsApi: Send + Sync {
     async fn validate_post_id(&self, post_id: &str) -> anyhow::Result<bool>;
+
+    /// Returns the post's metadata, or `None` if there is no such post.
+    async fn fetch_post(&self, post_id: &str) -> anyhow::Result<Option<Post>>;
+
+    /// Checks that the articles service can be reached.
+    async fn ping(&self) -> anyhow::Result<()>;
+}
+
+/// The metadata of a post, as returned by the articles service.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+pub struct Post {
+    /// Filled in from the requested ID, so the articles service needn't echo it.
+    #[serde(default)]
+    pub id: String,
+    #[serde(alias = "authorId")]
+    pub author_id: String,
+    #[serde(default)]
+    pub tags: Vec<String>,
+    #[serde(default, alias = "publishedAt")]
+    pub published_at: Option<DateTime<Utc>>,
+}
+
+/// Remembers lookups, including of missing posts, for a fixed time.
+#[derive(Debug)]
+struct PostCache {
+    ttl: Duration,
+    capacity: usize,
+    entries: Mutex<HashMap<String, (Instant, Option<Post>)>>,
+}
+
+impl PostCache {
+    fn new(ttl: Duration, capacity: usize) -> Self {
+        Self {
+            ttl,
+            capacity,
+            entries: Mutex::new(HashMap::new()),
+        }
+    }
+
+    fn get(&self, post_id: &str, now: Instant) -> Option<Option<Post>> {
+        let entries = self.entries.lock().unwrap();
+        entries
+            .get(post_id)
+            .filter(|(expires_at, _)| *expires_at > now)
+            .map(|(_, post)| post.clone())
+    }
+
+    fn insert(&self, post_id: &str, post: Option<Post>, now: Instant) {
+        if self.ttl.is_zero() || self.capacity == 0 {
+            return;
+        }
+
+        let mut entries = self.entries.lock().unwrap();
+        if entries.len() >= self.capacity && !entries.contains_key(post_id) {
+            entries.retain(|_, (expires_at, _)| *expires_at > now);
+            if entries.len() >= self.capacity {
+                // Still full of live entries: start over rather than track recency.
+                entries.clear();
+            }
+        }
+        entries.insert(post_id.to_owned(), (now + self.ttl, post));
+    }
 }
 
 #[derive(Debug, Clone)]
 pub struct PostsMicroserviceClient {
     base_url: Url,
     client: Client,
+    cache: Arc<PostCache>,
 }
 
 impl PostsMicroserviceClient {
@@ -18,23 +91,92 @@ impl PostsMicroserviceClient {
         Self {
             base_url: base_url.into_url().unwrap(),
             client: Client::new(),
+            cache: Arc::new(PostCache::new(Duration::ZERO, 0)),
         }
     }
+
+    /// Fails requests that take longer than `timeout`.
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.client = Client::builder().timeout(timeout).build().unwrap();
+        self
+    }
+
+    /// Keeps up to `capacity` fetched posts for `ttl`. Disabled by default.
+    pub fn with_cache(mut self, ttl: Duration, capacity: usize) -> Self {
+        self.cache = Arc::new(PostCache::new(ttl, capacity));
+        self
+    }
+
+    /// Builds the URL of an article, with the ID as a single path segment so
+    /// that IDs like `../x` or `//host/x` cannot point elsewhere. `.` and `..`
+    /// would still be resolved as relative segments, so they get no URL.
+    fn article_url(&self, post_id: &str) -> Option<Url> {
+        if post_id == "." || post_id == ".." {
+            return None;
+        }
+
+        let mut url = self.base_url.join("/articles/").unwrap();
+        url.path_segments_mut()
+            .unwrap()
+            .pop_if_empty()
+            .push(post_id);
+        Some(url)
+    }
 }
 
 #[async_trait]
 impl PostsApi for PostsMicroserviceClient {
     async fn validate_post_id(&self, post_id: &str) -> anyhow::Result<bool> {
-        let url = self
-            .base_url
-            .join("/articles/")
-            .and_then(|url| url.join(post_id))
-            .unwrap();
+        let Some(url) = self.article_url(post_id) else {
+            return Ok(false);
+        };
 
-        let res = self.client.get(url).send().await.map_err(|e| anyhow!(e))?;
+        let res = metrics::track_upstream("articles", "validate_post_id", self.client.get(url))
+            .await
+            .map_err(|e| anyhow!(e))?;
 
         Ok(res.status() == StatusCode::OK)
     }
+
+    async fn fetch_post(&self, post_id: &str) -> anyhow::Result<Option<Post>> {
+        if let Some(post) = self.cache.get(post_id, Instant::now()) {
+            return Ok(post);
+        }
+
+        let Some(url) = self.article_url(post_id) else {
+            return Ok(None);
+        };
+
+        let res = metrics::track_upstream("articles", "fetch_post", self.client.get(url))
+            .await
+            .map_err(|e| anyhow!(e))?;
+
+        let post = match res.status() {
+            StatusCode::NOT_FOUND => None,
+            status if status.is_success() => {
+                let mut post: Post = res.json().await.map_err(|e| anyhow!(e))?;
+                post.id = post_id.to_owned();
+                Some(post)
+            }
+            status => return Err(anyhow!("Articles service returned {status}")),
+        };
+
+        self.cache.insert(post_id, post.clone(), Instant::now());
+        Ok(post)
+    }
+
+    async fn ping(&self) -> anyhow::Result<()> {
+        let res =
+            metrics::track_upstream("articles", "ping", self.client.get(self.base_url.clone()))
+                .await
+                .map_err(|e| anyhow!(e))?;
+
+        if res.status().is_server_error() {
+            Err(anyhow!("Articles service returned {}", res.status()))
+        } else {
+            Ok(())
+        }
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -45,4 +187,115 @@ impl PostsApi for MockPostsClient {
     async fn validate_post_id(&self, post_id: &str) -> anyhow::Result<bool> {
         Ok(post_id.len() >= 10)
     }
+
+    async fn fetch_post(&self, post_id: &str) -> anyhow::Result<Option<Post>> {
+        if post_id.starts_with("unavailable") {
+            anyhow::bail!("Articles service unavailable");
+        }
+
+        // Every valid post belongs to the user `MockUsersClient` returns.
+        Ok((post_id.len() >= 10).then(|| Post {
+            id: post_id.to_owned(),
+            author_id: "1234567890".to_owned(),
+            tags: vec!["mock".to_owned()],
+            published_at: None,
+        }))
+    }
+
+    async fn ping(&self) -> anyhow::Result<()> {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn post(id: &str) -> Post {
+        Post {
+            id: id.to_owned(),
+            author_id: "author".to_owned(),
+            tags: Vec::new(),
+            published_at: None,
+        }
+    }
+
+    #[test]
+    fn decodes_camel_case_articles() {
+        let post: Post = serde_json::from_str(
+            r#"{"authorId": "42", "tags": ["rust"], "publishedAt": "2026-10-01T12:00:00Z", "title": "Hi"}"#,
+        )
+        .unwrap();
+
+        assert_eq!(post.author_id, "42");
+        assert_eq!(post.tags, ["rust"]);
+        assert_eq!(
+            post.published_at.unwrap().to_rfc3339(),
+            "2026-10-01T12:00:00+00:00"
+        );
+
+        let post: Post = serde_json::from_str(r#"{"author_id": "42"}"#).unwrap();
+        assert!(post.tags.is_empty() && post.published_at.is_none());
+    }
+
+    #[test]
+    fn article_urls_keep_the_id_in_one_segment() {
+        let client = PostsMicroserviceClient::new("http://articles/api/");
+        let url = |id| client.article_url(id).map(|url| url.to_string());
+
+        assert_eq!(url("abc").unwrap(), "http://articles/articles/abc");
+        assert_eq!(
+            url("../admin").unwrap(),
+            "http://articles/articles/..%2Fadmin"
+        );
+        assert_eq!(
+            url("//evil/x").unwrap(),
+            "http://articles/articles/%2F%2Fevil%2Fx"
+        );
+        assert_eq!(
+            url("https://evil/x").unwrap(),
+            "http://articles/articles/https:%2F%2Fevil%2Fx"
+        );
+        assert_eq!(url("a?b#c").unwrap(), "http://articles/articles/a%3Fb%23c");
+        assert_eq!(url(".."), None);
+        assert_eq!(url("."), None);
+    }
+
+    #[test]
+    fn cached_posts_expire() {
+        let cache = PostCache::new(Duration::from_secs(60), 10);
+        let now = Instant::now();
+
+        cache.insert("a", Some(post("a")), now);
+        cache.insert("missing", None, now);
+
+        assert_eq!(cache.get("a", now), Some(Some(post("a"))));
+        assert_eq!(cache.get("missing", now), Some(None));
+        assert_eq!(cache.get("b", now), None);
+        assert_eq!(cache.get("a", now + Duration::from_secs(61)), None);
+    }
+
+    #[test]
+    fn full_cache_drops_expired_entries_first() {
+        let cache = PostCache::new(Duration::from_secs(60), 2);
+        let now = Instant::now();
+
+        cache.insert("a", Some(post("a")), now);
+        cache.insert("b", Some(post("b")), now + Duration::from_secs(30));
+        cache.insert("c", Some(post("c")), now + Duration::from_secs(61));
+
+        let later = now + Duration::from_secs(61);
+        assert_eq!(cache.get("a", later), None);
+        assert!(cache.get("b", later).is_some());
+        assert!(cache.get("c", later).is_some());
+    }
+
+    #[test]
+    fn zero_ttl_disables_the_cache() {
+        let cache = PostCache::new(Duration::ZERO, 10);
+        let now = Instant::now();
+
+        cache.insert("a", Some(post("a")), now);
+        assert_eq!(cache.get("a", now), None);
+    }
 }
@@ -0,0 +1,370 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    sync::Arc,
+    time::Duration,
+};
+
+use chrono::{DateTime, Utc};
+use tokio::task::JoinHandle;
+
+use crate::{
+    db::{EventRepository, Interaction},
+    domain::{EventKind, Recommendation},
+};
+
+/// Posts only count as related when this many readers engaged with both.
+const MIN_COMMON_READERS: u32 = 2;
+
+/// How related posts are computed from the events of signed in readers.
+#[derive(Debug, Clone)]
+pub struct RecommendationPolicy {
+    pub interval: Duration,
+    /// Only events this recent are considered.
+    pub window: chrono::Duration,
+    /// Caps the posts considered per reader, keeping the strongest interactions.
+    pub max_posts_per_user: usize,
+    /// How many related posts are stored for each post.
+    pub related_per_post: usize,
+    /// How many interactions are read at once.
+    pub batch_size: u32,
+}
+
+impl Default for RecommendationPolicy {
+    fn default() -> Self {
+        Self {
+            interval: Duration::from_secs(60 * 60),
+            window: chrono::Duration::days(90),
+            max_posts_per_user: 50,
+            related_per_post: 20,
+            batch_size: 10_000,
+        }
+    }
+}
+
+fn weight(kind: EventKind) -> f64 {
+    match kind {
+        EventKind::View => 1.0,
+        EventKind::Like => 3.0,
+        EventKind::Share => 4.0,
+    }
+}
+
+/// Accumulates, one reader at a time, how much each pair of posts shares its
+/// readers, so that interactions can be read in pages rather than all at once.
+#[derive(Default)]
+struct Similarity {
+    ids: HashMap<String, usize>,
+    posts: Vec<String>,
+    /// The squared weights of each post's readers.
+    norms: Vec<f64>,
+    /// The dot product and number of common readers of each pair of posts.
+    pairs: HashMap<(usize, usize), (f64, u32)>,
+}
+
+impl Similarity {
+    fn id(&mut self, post_id: &str) -> usize {
+        if let Some(&id) = self.ids.get(post_id) {
+            return id;
+        }
+
+        let id = self.posts.len();
+        self.ids.insert(post_id.to_owned(), id);
+        self.posts.push(post_id.to_owned());
+        self.norms.push(0.0);
+        id
+    }
+
+    /// Adds the interactions of a single reader, weighting each post by the
+    /// strongest one.
+    fn add_reader(&mut self, interactions: &[Interaction], policy: &RecommendationPolicy) {
+        let mut weights: HashMap<&str, f64> = HashMap::new();
+        for interaction in interactions {
+            let weight = weight(interaction.kind);
+            let post = weights.entry(&interaction.post_id).or_default();
+            *post = post.max(weight);
+        }
+
+        let mut posts: Vec<(&str, f64)> = weights.into_iter().collect();
+        posts.sort_unstable_by(|(a, a_weight), (b, b_weight)| {
+            b_weight.total_cmp(a_weight).then_with(|| a.cmp(b))
+        });
+        posts.truncate(policy.max_posts_per_user);
+
+        let mut posts: Vec<(usize, f64)> = posts
+            .into_iter()
+            .map(|(post, weight)| (self.id(post), weight))
+            .collect();
+        // Sorted by ID so that each pair is counted under a single key.
+        posts.sort_unstable_by_key(|&(post, _)| post);
+
+        for (i, &(a, a_weight)) in posts.iter().enumerate() {
+            self.norms[a] += a_weight * a_weight;
+
+            for &(b, b_weight) in &posts[i + 1..] {
+                let (dot, readers) = self.pairs.entry((a, b)).or_default();
+                *dot += a_weight * b_weight;
+                *readers += 1;
+            }
+        }
+    }
+
+    /// Keeps the best `policy.related_per_post` related posts of each post.
+    fn related(self, policy: &RecommendationPolicy) -> BTreeMap<String, Vec<Recommendation>> {
+        let mut related: BTreeMap<String, Vec<Recommendation>> = BTreeMap::new();
+        for ((a, b), (dot, readers)) in self.pairs {
+            if readers < MIN_COMMON_READERS {
+                continue;
+            }
+
+            let score = dot / (self.norms[a].sqrt() * self.norms[b].sqrt());
+            for (post, other) in [(a, b), (b, a)] {
+                related
+                    .entry(self.posts[post].clone())
+                    .or_default()
+                    .push(Recommendation {
+                        post_id: self.posts[other].clone(),
+                        score,
+                    });
+            }
+        }
+
+        for recommendations in related.values_mut() {
+            recommendations.sort_unstable_by(|a, b| {
+                b.score
+                    .total_cmp(&a.score)
+                    .then_with(|| a.post_id.cmp(&b.post_id))
+            });
+            recommendations.truncate(policy.related_per_post);
+        }
+
+        related
+    }
+}
+
+/// Recomputes the related posts from recent events and replaces the stored
+/// ones, returning for how many posts related ones were found.
+///
+/// Interactions are read in pages ordered by reader, and only the current
+/// reader's are kept, so memory grows with the number of posts rather than
+/// with the number of events.
+pub async fn refresh(
+    repo: &dyn EventRepository,
+    policy: &RecommendationPolicy,
+) -> anyhow::Result<usize> {
+    let since = Utc::now()
+        .checked_sub_signed(policy.window)
+        .unwrap_or(DateTime::<Utc>::MIN_UTC);
+
+    let mut similarity = Similarity::default();
+    let mut reader: Vec<Interaction> = Vec::new();
+    let mut after: Option<Interaction> = None;
+
+    loop {
+        let page = repo
+            .find_interactions(since, after.as_ref(), policy.batch_size)
+            .await?;
+        let last_page = page.len() < policy.batch_size as usize;
+        after = page.last().cloned();
+
+        let task_policy = policy.clone();
+        (similarity, reader) = tokio::task::spawn_blocking(move || {
+            for interaction in page {
+                if reader
+                    .first()
+                    .is_some_and(|r| r.user_id != interaction.user_id)
+                {
+                    similarity.add_reader(&reader, &task_policy);
+                    reader.clear();
+                }
+                reader.push(interaction);
+            }
+            (similarity, reader)
+        })
+        .await?;
+
+        if last_page {
+            break;
+        }
+    }
+
+    let policy = policy.clone();
+    let related = tokio::task::spawn_blocking(move || {
+        similarity.add_reader(&reader, &policy);
+        similarity.related(&policy)
+    })
+    .await?;
+
+    repo.replace_related_posts(&related).await?;
+    Ok(related.len())
+}
+
+/// Spawns a task refreshing the related posts every `policy.interval`.
+pub fn spawn(repo: Arc<dyn EventRepository>, policy: RecommendationPolicy) -> JoinHandle<()> {
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(policy.interval);
+
+        loop {
+            interval.tick().await;
+
+            match refresh(repo.as_ref(), &policy).await {
+                Ok(count) => tracing::info!("Refreshed the related posts of {count} posts"),
+                Err(e) => tracing::error!("Failed to refresh related posts: {e:?}"),
+            }
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn interaction(user_id: &str, post_id: &str, kind: EventKind) -> Interaction {
+        Interaction {
+            user_id: user_id.to_owned(),
+            post_id: post_id.to_owned(),
+            kind,
+        }
+    }
+
+    /// Scores every pair of posts by the cosine similarity of their readers, each
+    /// reader weighted by their strongest interaction with the post, and keeps
+    /// the best `policy.related_per_post` for each post.
+    fn related_posts(
+        interactions: &[Interaction],
+        policy: &RecommendationPolicy,
+    ) -> BTreeMap<String, Vec<Recommendation>> {
+        let mut readers: HashMap<&str, Vec<Interaction>> = HashMap::new();
+        for interaction in interactions {
+            readers
+                .entry(&interaction.user_id)
+                .or_default()
+                .push(interaction.clone());
+        }
+
+        let mut similarity = Similarity::default();
+        for interactions in readers.values() {
+            similarity.add_reader(interactions, policy);
+        }
+        similarity.related(policy)
+    }
+
+    fn related_ids(related: &BTreeMap<String, Vec<Recommendation>>, post_id: &str) -> Vec<String> {
+        related
+            .get(post_id)
+            .map(|posts| posts.iter().map(|p| p.post_id.clone()).collect())
+            .unwrap_or_default()
+    }
+
+    #[test]
+    fn relates_posts_read_by_the_same_readers() {
+        let interactions = [
+            interaction("ana", "a", EventKind::View),
+            interaction("ana", "b", EventKind::Like),
+            interaction("ana", "c", EventKind::View),
+            interaction("bob", "a", EventKind::Like),
+            interaction("bob", "b", EventKind::Share),
+            interaction("eve", "a", EventKind::View),
+            interaction("eve", "c", EventKind::View),
+        ];
+
+        let related = related_posts(&interactions, &RecommendationPolicy::default());
+
+        assert_eq!(related_ids(&related, "a"), ["b", "c"]);
+        assert_eq!(related_ids(&related, "b"), ["a"]);
+        // Only ana read both `b` and `c`.
+        assert_eq!(related_ids(&related, "c"), ["a"]);
+
+        let score = related["a"][0].score;
+        assert!(score > 0.0 && score <= 1.0);
+        assert_eq!(related["b"][0].score, score);
+    }
+
+    #[test]
+    fn repeated_events_count_once_with_the_strongest_kind() {
+        let once = [
+            interaction("ana", "a", EventKind::Like),
+            interaction("ana", "b", EventKind::View),
+            interaction("bob", "a", EventKind::Like),
+            interaction("bob", "b", EventKind::View),
+        ];
+        let repeated = [
+            &once[..],
+            &[
+                interaction("ana", "a", EventKind::View),
+                interaction("ana", "b", EventKind::View),
+            ],
+        ]
+        .concat();
+
+        let policy = RecommendationPolicy::default();
+        assert_eq!(
+            related_posts(&once, &policy),
+            related_posts(&repeated, &policy)
+        );
+    }
+
+    #[test]
+    fn keeps_the_best_related_posts() {
+        let mut interactions = Vec::new();
+        for user in ["ana", "bob", "eve"] {
+            interactions.push(interaction(user, "a", EventKind::View));
+            interactions.push(interaction(user, "b", EventKind::Share));
+        }
+        for user in ["ana", "bob"] {
+            interactions.push(interaction(user, "c", EventKind::View));
+        }
+
+        let policy = RecommendationPolicy {
+            related_per_post: 1,
+            ..Default::default()
+        };
+        let related = related_posts(&interactions, &policy);
+
+        assert_eq!(related_ids(&related, "a"), ["b"]);
+        assert_eq!(related_ids(&related, "b"), ["a"]);
+    }
+
+    #[tokio::test]
+    async fn refreshing_in_pages_matches_reading_everything() {
+        use crate::{db::NewEvent, testing::MemoryRepository};
+
+        let mut interactions = Vec::new();
+        for (user, posts) in [
+            ("ana", ["a", "b", "c"]),
+            ("bob", ["a", "b", "d"]),
+            ("eve", ["a", "c", "d"]),
+            ("joe", ["b", "c", "d"]),
+        ] {
+            for post in posts {
+                interactions.push(interaction(user, post, EventKind::View));
+                interactions.push(interaction(user, post, EventKind::Like));
+            }
+        }
+
+        let repo = MemoryRepository::default();
+        let events: Vec<NewEvent> = interactions
+            .iter()
+            .map(|i| NewEvent {
+                post_id: i.post_id.clone(),
+                kind: i.kind,
+                device: None,
+                user_id: Some(i.user_id.clone()),
+                referrer: None,
+                timestamp: Utc::now(),
+            })
+            .collect();
+        repo.create_events(&events).await.unwrap();
+
+        // Pages of 5 split readers across pages, and end on a view whose like
+        // is on the next page.
+        let policy = RecommendationPolicy {
+            batch_size: 5,
+            ..Default::default()
+        };
+        let count = refresh(&repo, &policy).await.unwrap();
+
+        let expected = related_posts(&interactions, &policy);
+        assert_eq!(count, 4);
+        assert_eq!(*repo.related.lock().unwrap(), expected);
+    }
+}
@@ -0,0 +1,170 @@
+use std::{sync::Arc, time::Duration};
+
+use chrono::{DateTime, Utc};
+use tokio::task::JoinHandle;
+
+use crate::{db::EventRepository, domain::EventKind};
+
+/// How long raw events of each kind are kept before being rolled up into daily
+/// aggregates. `None` keeps them forever.
+#[derive(Debug, Clone)]
+pub struct RetentionPolicy {
+    pub views: Option<chrono::Duration>,
+    pub likes: Option<chrono::Duration>,
+    pub shares: Option<chrono::Duration>,
+    pub interval: Duration,
+    pub batch_size: u32,
+}
+
+impl Default for RetentionPolicy {
+    fn default() -> Self {
+        Self {
+            views: Some(chrono::Duration::days(90)),
+            likes: None,
+            shares: None,
+            interval: Duration::from_secs(60 * 60),
+            batch_size: 1000,
+        }
+    }
+}
+
+impl RetentionPolicy {
+    fn max_age(&self, kind: EventKind) -> Option<chrono::Duration> {
+        match kind {
+            EventKind::View => self.views,
+            EventKind::Like => self.likes,
+            EventKind::Share => self.shares,
+        }
+    }
+
+    pub fn keeps_everything(&self) -> bool {
+        self.views.is_none() && self.likes.is_none() && self.shares.is_none()
+    }
+}
+
+/// Rolls up expired events once, in batches, returning how many were purged.
+pub async fn purge_expired(
+    repo: &dyn EventRepository,
+    policy: &RetentionPolicy,
+) -> anyhow::Result<u64> {
+    let mut purged = 0;
+
+    for kind in [EventKind::View, EventKind::Like, EventKind::Share] {
+        let Some(max_age) = policy.max_age(kind) else {
+            continue;
+        };
+
+        // No event can be older than the earliest representable date.
+        let Some(before) = Utc::now().checked_sub_signed(max_age) else {
+            continue;
+        };
+
+        purged += purge_before(repo, kind, before, policy.batch_size).await?;
+    }
+
+    Ok(purged)
+}
+
+/// Rolls up every event of `kind` older than `before`, in batches of
+/// `batch_size`, returning how many were purged.
+pub async fn purge_before(
+    repo: &dyn EventRepository,
+    kind: EventKind,
+    before: DateTime<Utc>,
+    batch_size: u32,
+) -> anyhow::Result<u64> {
+    anyhow::ensure!(batch_size > 0, "The batch size must be greater than zero");
+
+    let mut purged = 0;
+
+    loop {
+        let count = repo.roll_up_events(kind, before, batch_size).await?;
+        purged += count;
+
+        if count < batch_size as u64 {
+            break;
+        }
+
+        // Give other queries a chance between batches.
+        tokio::task::yield_now().await;
+    }
+
+    Ok(purged)
+}
+
+/// Spawns a task enforcing `policy` every `policy.interval`.
+pub fn spawn(repo: Arc<dyn EventRepository>, policy: RetentionPolicy) -> JoinHandle<()> {
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(policy.interval);
+
+        loop {
+            interval.tick().await;
+
+            match purge_expired(repo.as_ref(), &policy).await {
+                Ok(0) => {}
+                Ok(count) => tracing::info!("Rolled up {count} expired events"),
+                Err(e) => tracing::error!("Failed to purge expired events: {e:?}"),
+            }
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::Ordering;
+
+    use super::*;
+    use crate::testing::MemoryRepository;
+
+    #[tokio::test]
+    async fn rolls_up_in_batches_until_a_short_one() {
+        let repo = MemoryRepository::default();
+        repo.expired_events.store(2500, Ordering::SeqCst);
+
+        let purged = purge_before(&repo, EventKind::View, Utc::now(), 1000)
+            .await
+            .unwrap();
+
+        assert_eq!(purged, 2500);
+        assert_eq!(*repo.roll_ups.lock().unwrap(), [1000, 1000, 500]);
+    }
+
+    #[tokio::test]
+    async fn stops_after_an_empty_batch() {
+        let repo = MemoryRepository::default();
+        repo.expired_events.store(1000, Ordering::SeqCst);
+
+        let purged = purge_before(&repo, EventKind::View, Utc::now(), 1000)
+            .await
+            .unwrap();
+
+        assert_eq!(purged, 1000);
+        assert_eq!(*repo.roll_ups.lock().unwrap(), [1000, 0]);
+    }
+
+    #[tokio::test]
+    async fn rejects_empty_batches() {
+        let repo = MemoryRepository::default();
+        repo.expired_events.store(1, Ordering::SeqCst);
+
+        assert!(
+            purge_before(&repo, EventKind::View, Utc::now(), 0)
+                .await
+                .is_err()
+        );
+        assert!(repo.roll_ups.lock().unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn skips_ages_before_the_earliest_date() {
+        let repo = MemoryRepository::default();
+        repo.expired_events.store(1, Ordering::SeqCst);
+
+        let policy = RetentionPolicy {
+            views: Some(chrono::Duration::days(u32::MAX.into())),
+            ..Default::default()
+        };
+
+        assert_eq!(purge_expired(&repo, &policy).await.unwrap(), 0);
+    }
+}
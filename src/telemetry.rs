@@ -0,0 +1,128 @@
+use std::env;
+
+use axum::http::HeaderMap;
+use opentelemetry::{Context, global, trace::TracerProvider};
+use opentelemetry_http::{HeaderExtractor, HeaderInjector};
+use opentelemetry_otlp::SpanExporter;
+use opentelemetry_sdk::{Resource, propagation::TraceContextPropagator, trace::SdkTracerProvider};
+use tokio::{
+    sync::oneshot,
+    time::{Instant, timeout_at},
+};
+use tracing_opentelemetry::OpenTelemetrySpanExt;
+use tracing_subscriber::{
+    EnvFilter, Layer, fmt::writer::BoxMakeWriter, layer::SubscriberExt, util::SubscriberInitExt,
+};
+
+pub struct Telemetry {
+    provider: Option<SdkTracerProvider>,
+}
+
+impl Telemetry {
+    /// Flushes pending spans, giving up at `deadline` if there is one.
+    pub async fn shutdown(self, deadline: Option<Instant>) {
+        let Some(provider) = self.provider else {
+            return;
+        };
+
+        // Unlike a blocking task, an abandoned thread doesn't hold up exit.
+        let (done, flushed) = oneshot::channel();
+        std::thread::spawn(move || done.send(provider.shutdown()).ok());
+
+        let result = match deadline {
+            Some(deadline) => match timeout_at(deadline, flushed).await {
+                Ok(result) => result,
+                Err(_) => {
+                    tracing::warn!(
+                        "Traces still unflushed at the shutdown deadline, dropping them"
+                    );
+                    return;
+                }
+            },
+            None => flushed.await,
+        };
+        if let Ok(Err(e)) = result {
+            tracing::warn!("Failed to flush traces: {e}");
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub enum LogOutput {
+    Stdout,
+    /// For commands that print their results to stdout.
+    Stderr,
+}
+
+/// Logs to `output`, as JSON when `LOG_FORMAT=json`, and exports spans when
+/// `OTEL_EXPORTER_OTLP_*` is set.
+///
+/// Reads the environment rather than [`crate::config::Config`], which is only
+/// loaded after this.
+pub fn init(output: LogOutput) -> anyhow::Result<Telemetry> {
+    let filter = EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new("info"));
+
+    let writer = match output {
+        LogOutput::Stdout => BoxMakeWriter::new(std::io::stdout),
+        LogOutput::Stderr => BoxMakeWriter::new(std::io::stderr),
+    };
+    let fmt = tracing_subscriber::fmt::layer().with_writer(writer);
+
+    let fmt = match env::var("LOG_FORMAT").as_deref() {
+        Ok("json") => fmt
+            .json()
+            .flatten_event(true)
+            .with_current_span(true)
+            .boxed(),
+        Ok("text") | Err(_) => fmt.boxed(),
+        Ok(other) => return Err(anyhow::anyhow!("Unknown LOG_FORMAT: {other}")),
+    };
+
+    global::set_text_map_propagator(TraceContextPropagator::new());
+
+    let provider = otlp_enabled().then(tracer_provider).transpose()?;
+    let otel = provider
+        .as_ref()
+        .map(|p| tracing_opentelemetry::layer().with_tracer(p.tracer("mittel-engagement")));
+
+    tracing_subscriber::registry()
+        .with(fmt)
+        .with(otel)
+        .with(filter)
+        .try_init()?;
+
+    Ok(Telemetry { provider })
+}
+
+fn otlp_enabled() -> bool {
+    env::var_os("OTEL_EXPORTER_OTLP_ENDPOINT").is_some()
+        || env::var_os("OTEL_EXPORTER_OTLP_TRACES_ENDPOINT").is_some()
+}
+
+fn tracer_provider() -> anyhow::Result<SdkTracerProvider> {
+    // The endpoint, headers and timeout are read from the standard variables.
+    let exporter = SpanExporter::builder().with_http().build()?;
+
+    let service_name =
+        env::var("OTEL_SERVICE_NAME").unwrap_or_else(|_| "mittel-engagement".to_owned());
+
+    Ok(SdkTracerProvider::builder()
+        .with_batch_exporter(exporter)
+        .with_resource(Resource::builder().with_service_name(service_name).build())
+        .build())
+}
+
+pub fn extract_context(headers: &HeaderMap) -> Context {
+    global::get_text_map_propagator(|propagator| propagator.extract(&HeaderExtractor(headers)))
+}
+
+pub fn inject_context(request: reqwest::RequestBuilder) -> reqwest::RequestBuilder {
+    let context = tracing::Span::current().context();
+    let mut headers = HeaderMap::new();
+
+    global::get_text_map_propagator(|propagator| {
+        propagator.inject_context(&context, &mut HeaderInjector(&mut headers))
+    });
+
+    request.headers(headers)
+}
@@ -0,0 +1,538 @@
+//! In-memory fakes for exercising the HTTP API and background tasks without a
+//! database.
+
+use std::{
+    collections::{BTreeMap, HashMap},
+    sync::{
+        Arc, Mutex,
+        atomic::{AtomicBool, AtomicU32, AtomicU64, Ordering},
+    },
+};
+
+use async_trait::async_trait;
+use axum::Router;
+use chrono::{DateTime, NaiveDate, Utc};
+use futures_util::stream::{self, BoxStream};
+
+use crate::{
+    config::Config,
+    db::{
+        DeviceRequest, EventRepository, Interaction, MigrationInfo, NewEvent, PoolStats,
+        rank_engagement,
+    },
+    domain::{
+        AuthorEngagement, Device, ErasureMode, ErasureReport, Event, EventKind, EventSummary,
+        HistoryEntry, PostAnalytics, PurgeMode, PurgeReport, Recommendation, TagEngagement,
+    },
+    http::{
+        self,
+        auth::{ApiKey, ApiKeyStore},
+        state::AppState,
+    },
+    ingest::EventIngestor,
+    live::EventHub,
+    posts::{MockPostsClient, Post},
+    users::MockUsersClient,
+};
+
+/// Saved post metadata and when it was saved.
+pub type SavedMetadata = (Option<Post>, DateTime<Utc>);
+
+/// Keeps written events in memory and can be told to fail writes.
+#[derive(Default)]
+pub struct MemoryRepository {
+    pub events: Mutex<Vec<NewEvent>>,
+    /// Sizes of the batches written so far.
+    pub batches: Mutex<Vec<usize>>,
+    /// How many of the next writes fail.
+    pub failing_writes: AtomicU32,
+    /// Whether pings fail, as when the database is down.
+    pub unreachable: AtomicBool,
+    /// How many events are left to roll up.
+    pub expired_events: AtomicU64,
+    /// Sizes of the roll ups done so far.
+    pub roll_ups: Mutex<Vec<u64>>,
+    pub related: Mutex<BTreeMap<String, Vec<Recommendation>>>,
+    pub metadata: Mutex<BTreeMap<String, SavedMetadata>>,
+    /// How many times each post's metadata was saved.
+    pub metadata_saves: Mutex<BTreeMap<String, u32>>,
+}
+
+impl MemoryRepository {
+    pub fn fail_writes(&self, count: u32) {
+        self.failing_writes.store(count, Ordering::SeqCst);
+    }
+
+    pub fn set_unreachable(&self, unreachable: bool) {
+        self.unreachable.store(unreachable, Ordering::SeqCst);
+    }
+
+    pub fn event_count(&self) -> usize {
+        self.events.lock().unwrap().len()
+    }
+
+    /// Device IDs of the stored events, numbering each distinct device in the
+    /// order it was first seen.
+    fn device_ids(events: &[NewEvent]) -> Vec<Option<i32>> {
+        let mut seen: Vec<&DeviceRequest> = Vec::new();
+        events
+            .iter()
+            .map(|event| {
+                let device = event.device.as_ref()?;
+                let index = seen.iter().position(|d| *d == device).unwrap_or_else(|| {
+                    seen.push(device);
+                    seen.len() - 1
+                });
+                Some(index as i32 + 1)
+            })
+            .collect()
+    }
+
+    /// The devices of the stored events that `keep` selects.
+    fn devices(&self, keep: impl Fn(&NewEvent) -> bool) -> Vec<Device> {
+        let events = self.events.lock().unwrap();
+        let mut devices: Vec<Device> = Vec::new();
+        for (event, id) in events.iter().zip(Self::device_ids(&events)) {
+            let (Some(device), Some(id)) = (&event.device, id) else {
+                continue;
+            };
+            if keep(event) && !devices.iter().any(|d| d.id == i64::from(id)) {
+                devices.push(Device {
+                    id: id.into(),
+                    os: Some(device.os.clone()),
+                    browser: Some(device.browser.clone()),
+                    language: Some(device.language.clone()),
+                    screen_resolution: Some(device.screen_resolution.clone()),
+                });
+            }
+        }
+        devices
+    }
+
+    /// The stored events that `keep` selects, numbered from 1 in the order they
+    /// were written.
+    fn stored_events(&self, keep: impl Fn(&NewEvent) -> bool) -> Vec<Event> {
+        let events = self.events.lock().unwrap();
+        events
+            .iter()
+            .zip(Self::device_ids(&events))
+            .enumerate()
+            .filter(|(_, (event, _))| keep(event))
+            .map(|(i, (event, device_id))| Event {
+                id: i as i64 + 1,
+                device_id,
+                user_id: event.user_id.clone(),
+                post_id: event.post_id.clone(),
+                kind: event.kind,
+                referrer: event.referrer.clone(),
+                timestamp: event.timestamp,
+            })
+            .collect()
+    }
+
+    /// Totals the events of the days in `from..to` by the keys `key_of` gives
+    /// each post's metadata, as the engagement queries do.
+    fn rank_by_metadata(
+        &self,
+        from: NaiveDate,
+        to: NaiveDate,
+        limit: usize,
+        key_of: impl Fn(&Post) -> Vec<String>,
+    ) -> Vec<(String, EventSummary)> {
+        let metadata = self.metadata.lock().unwrap();
+        let rows: Vec<(String, EventKind, i64)> = self
+            .events
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|event| {
+                let day = event.timestamp.date_naive();
+                day >= from && day < to
+            })
+            .flat_map(|event| {
+                let keys = match metadata.get(&event.post_id) {
+                    Some((Some(post), _)) => key_of(post),
+                    _ => Vec::new(),
+                };
+                keys.into_iter().map(|key| (key, event.kind, 1))
+            })
+            .collect();
+        rank_engagement(rows, limit)
+    }
+}
+
+#[async_trait]
+impl EventRepository for MemoryRepository {
+    async fn ping(&self) -> anyhow::Result<()> {
+        if self.unreachable.load(Ordering::SeqCst) {
+            anyhow::bail!("Can't connect to MySQL server on 'db.internal:3306'");
+        }
+        Ok(())
+    }
+
+    fn pool_stats(&self) -> PoolStats {
+        PoolStats {
+            max: 1,
+            size: 0,
+            idle: 0,
+        }
+    }
+
+    async fn migration_status(&self) -> anyhow::Result<Vec<MigrationInfo>> {
+        Ok(Vec::new())
+    }
+
+    async fn find_events(
+        &self,
+        _user_id: &Option<String>,
+        _post_id: &Option<String>,
+    ) -> anyhow::Result<Vec<Event>> {
+        Ok(Vec::new())
+    }
+
+    async fn find_event_summary(&self, post_id: &str) -> anyhow::Result<EventSummary> {
+        let events = self.events.lock().unwrap();
+        let mut summary = EventSummary::default();
+        for event in events.iter().filter(|e| e.post_id == post_id) {
+            match event.kind {
+                EventKind::View => summary.views += 1,
+                EventKind::Like => summary.likes += 1,
+                EventKind::Share => summary.shares += 1,
+            }
+        }
+        Ok(summary)
+    }
+
+    async fn find_event_summaries(&self, post_ids: &[&str]) -> anyhow::Result<Vec<EventSummary>> {
+        let mut summaries = Vec::new();
+        for post_id in post_ids {
+            summaries.push(self.find_event_summary(post_id).await?);
+        }
+        Ok(summaries)
+    }
+
+    async fn find_post_analytics(
+        &self,
+        post_id: &str,
+        _from: NaiveDate,
+        _to: NaiveDate,
+    ) -> anyhow::Result<PostAnalytics> {
+        Ok(PostAnalytics {
+            post_id: post_id.to_owned(),
+            summary: self.find_event_summary(post_id).await?,
+            daily: Vec::new(),
+            operating_systems: Vec::new(),
+            browsers: Vec::new(),
+            referrers: Vec::new(),
+        })
+    }
+
+    async fn create_events(&self, events: &[NewEvent]) -> anyhow::Result<()> {
+        let failing = self
+            .failing_writes
+            .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |n| n.checked_sub(1));
+        if failing.is_ok() {
+            anyhow::bail!("Write failed");
+        }
+
+        self.batches.lock().unwrap().push(events.len());
+        self.events.lock().unwrap().extend_from_slice(events);
+        Ok(())
+    }
+
+    async fn find_devices(&self) -> anyhow::Result<Vec<Device>> {
+        Ok(self.devices(|_| true))
+    }
+
+    fn stream_user_events(&self, user_id: String) -> BoxStream<'static, anyhow::Result<Event>> {
+        let events = self.stored_events(|event| event.user_id.as_ref() == Some(&user_id));
+        Box::pin(stream::iter(events.into_iter().map(Ok)))
+    }
+
+    fn stream_post_events(&self, post_id: String) -> BoxStream<'static, anyhow::Result<Event>> {
+        let events = self.stored_events(|event| event.post_id == post_id);
+        Box::pin(stream::iter(events.into_iter().map(Ok)))
+    }
+
+    async fn find_user_devices(&self, user_id: &str) -> anyhow::Result<Vec<Device>> {
+        Ok(self.devices(|event| event.user_id.as_deref() == Some(user_id)))
+    }
+
+    async fn find_user_event_summary(&self, user_id: &str) -> anyhow::Result<EventSummary> {
+        let events = self.events.lock().unwrap();
+        let mut summary = EventSummary::default();
+        for event in events
+            .iter()
+            .filter(|e| e.user_id.as_deref() == Some(user_id))
+        {
+            match event.kind {
+                EventKind::View => summary.views += 1,
+                EventKind::Like => summary.likes += 1,
+                EventKind::Share => summary.shares += 1,
+            }
+        }
+        Ok(summary)
+    }
+
+    async fn find_user_history(
+        &self,
+        user_id: &str,
+        kind: Option<EventKind>,
+        before: Option<i64>,
+        limit: u32,
+    ) -> anyhow::Result<Vec<HistoryEntry>> {
+        // Event IDs are positions in `events`, starting at 1.
+        let mut entries: HashMap<(String, EventKind), HistoryEntry> = HashMap::new();
+        for (i, event) in self.events.lock().unwrap().iter().enumerate() {
+            if event.user_id.as_deref() != Some(user_id) || kind.is_some_and(|k| k != event.kind) {
+                continue;
+            }
+
+            let entry = entries
+                .entry((event.post_id.clone(), event.kind))
+                .or_insert_with(|| HistoryEntry {
+                    id: 0,
+                    post_id: event.post_id.clone(),
+                    kind: event.kind,
+                    timestamp: event.timestamp,
+                    events: 0,
+                });
+            entry.id = i as i64 + 1;
+            entry.timestamp = entry.timestamp.max(event.timestamp);
+            entry.events += 1;
+        }
+
+        let mut entries: Vec<_> = entries
+            .into_values()
+            .filter(|entry| before.is_none_or(|before| entry.id < before))
+            .collect();
+        entries.sort_unstable_by_key(|entry| std::cmp::Reverse(entry.id));
+        entries.truncate(limit as usize);
+        Ok(entries)
+    }
+
+    async fn erase_user_events(
+        &self,
+        user_id: &str,
+        mode: ErasureMode,
+    ) -> anyhow::Result<ErasureReport> {
+        let mut events = self.events.lock().unwrap();
+        let before = events.len();
+        let erased = match mode {
+            ErasureMode::Delete => {
+                events.retain(|event| event.user_id.as_deref() != Some(user_id));
+                before - events.len()
+            }
+            ErasureMode::Anonymize => events
+                .iter_mut()
+                .filter(|event| event.user_id.as_deref() == Some(user_id))
+                .map(|event| event.user_id = None)
+                .count(),
+        };
+        Ok(ErasureReport {
+            mode,
+            events: erased as u64,
+        })
+    }
+
+    async fn purge_post_events(
+        &self,
+        post_id: &str,
+        mode: PurgeMode,
+    ) -> anyhow::Result<PurgeReport> {
+        // Archived events are not kept, as nothing reads them back here.
+        let mut events = self.events.lock().unwrap();
+        let before = events.len();
+        events.retain(|event| event.post_id != post_id);
+        Ok(PurgeReport {
+            mode,
+            events: (before - events.len()) as u64,
+        })
+    }
+
+    async fn roll_up_events(
+        &self,
+        _kind: EventKind,
+        _before: DateTime<Utc>,
+        limit: u32,
+    ) -> anyhow::Result<u64> {
+        let left = self.expired_events.load(Ordering::SeqCst);
+        let count = left.min(limit.into());
+        self.expired_events.store(left - count, Ordering::SeqCst);
+
+        self.roll_ups.lock().unwrap().push(count);
+        Ok(count)
+    }
+
+    async fn rebuild_counters(&self, _post_id: Option<&str>) -> anyhow::Result<u64> {
+        Ok(0)
+    }
+
+    async fn find_stale_post_metadata(
+        &self,
+        refreshed_before: DateTime<Utc>,
+        limit: u32,
+    ) -> anyhow::Result<Vec<String>> {
+        let metadata = self.metadata.lock().unwrap();
+        let mut post_ids: Vec<String> = self
+            .events
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|event| event.post_id.clone())
+            .collect::<std::collections::BTreeSet<_>>()
+            .into_iter()
+            .filter(|post_id| {
+                metadata
+                    .get(post_id)
+                    .is_none_or(|(_, refreshed_at)| *refreshed_at < refreshed_before)
+            })
+            .collect();
+        // Missing metadata first, as nulls sort first in MySQL.
+        post_ids.sort_by_key(|post_id| metadata.get(post_id).map(|(_, at)| *at));
+        post_ids.truncate(limit as usize);
+        Ok(post_ids)
+    }
+
+    async fn find_author_post_ids(
+        &self,
+        _author_id: &str,
+        _limit: u32,
+    ) -> anyhow::Result<Vec<String>> {
+        Ok(Vec::new())
+    }
+
+    async fn save_post_metadata(&self, post_id: &str, post: Option<&Post>) -> anyhow::Result<()> {
+        self.metadata
+            .lock()
+            .unwrap()
+            .insert(post_id.to_owned(), (post.cloned(), Utc::now()));
+        *self
+            .metadata_saves
+            .lock()
+            .unwrap()
+            .entry(post_id.to_owned())
+            .or_default() += 1;
+        Ok(())
+    }
+
+    async fn find_tag_engagement(
+        &self,
+        from: NaiveDate,
+        to: NaiveDate,
+        limit: usize,
+    ) -> anyhow::Result<Vec<TagEngagement>> {
+        Ok(self
+            .rank_by_metadata(from, to, limit, |post| post.tags.clone())
+            .into_iter()
+            .map(|(tag, summary)| TagEngagement { tag, summary })
+            .collect())
+    }
+
+    async fn find_author_engagement(
+        &self,
+        from: NaiveDate,
+        to: NaiveDate,
+        limit: usize,
+    ) -> anyhow::Result<Vec<AuthorEngagement>> {
+        Ok(self
+            .rank_by_metadata(from, to, limit, |post| vec![post.author_id.clone()])
+            .into_iter()
+            .map(|(author_id, summary)| AuthorEngagement { author_id, summary })
+            .collect())
+    }
+
+    async fn find_interactions(
+        &self,
+        since: DateTime<Utc>,
+        after: Option<&Interaction>,
+        limit: u32,
+    ) -> anyhow::Result<Vec<Interaction>> {
+        // The query compares kinds by their enum index, in declaration order.
+        let key = |i: &Interaction| (i.user_id.clone(), i.post_id.clone(), i.kind as u8 + 1);
+
+        let mut interactions: Vec<Interaction> = self
+            .events
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|event| event.timestamp >= since)
+            .filter_map(|event| {
+                Some(Interaction {
+                    user_id: event.user_id.clone()?,
+                    post_id: event.post_id.clone(),
+                    kind: event.kind,
+                })
+            })
+            .filter(|i| after.is_none_or(|after| key(i) > key(after)))
+            .collect();
+        interactions.sort_unstable_by_key(key);
+        interactions.dedup_by_key(|i| key(i));
+        interactions.truncate(limit as usize);
+        Ok(interactions)
+    }
+
+    async fn replace_related_posts(
+        &self,
+        related: &BTreeMap<String, Vec<Recommendation>>,
+    ) -> anyhow::Result<()> {
+        *self.related.lock().unwrap() = related.clone();
+        Ok(())
+    }
+
+    async fn find_related_posts(
+        &self,
+        _post_id: &str,
+        _limit: u32,
+    ) -> anyhow::Result<Vec<Recommendation>> {
+        Ok(Vec::new())
+    }
+
+    async fn find_recommendations(
+        &self,
+        _user_id: &str,
+        _limit: u32,
+    ) -> anyhow::Result<Vec<Recommendation>> {
+        Ok(Vec::new())
+    }
+}
+
+/// Builds the API on top of `repo`, with the mock users and posts clients and
+/// the given private API keys.
+pub fn app(repo: Arc<MemoryRepository>, keys: Vec<ApiKey>, config: &Config) -> TestApp {
+    let live = Arc::new(EventHub::default());
+    let ingest = Arc::new(EventIngestor::spawn(
+        repo.clone(),
+        config.ingest_config(),
+        live.clone(),
+    ));
+
+    let state = Arc::new(AppState {
+        repo,
+        users: Arc::new(MockUsersClient),
+        posts: Arc::new(MockPostsClient),
+        ingest: ingest.clone(),
+        live,
+        erasure_mode: ErasureMode::default(),
+        purge_mode: PurgeMode::default(),
+        catalog_enabled: config.features.catalog,
+    });
+
+    let keys = Arc::new(ApiKeyStore::from_keys(keys));
+    let router = http::app(keys, config).with_state(state);
+
+    TestApp { router, ingest }
+}
+
+pub struct TestApp {
+    pub router: Router,
+    pub ingest: Arc<EventIngestor>,
+}
+
+pub fn api_key(token: &str, scopes: Vec<http::auth::Scope>) -> ApiKey {
+    ApiKey {
+        name: token.to_owned(),
+        token: token.to_owned(),
+        scopes,
+    }
+}
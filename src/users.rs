@@ -1,4 +1,4 @@
-use std::collections::HashMap;
+use std::{collections::HashMap, time::Duration};
 
 use anyhow::anyhow;
 use async_trait::async_trait;
@@ -6,7 +6,7 @@ use reqwest::{Client, IntoUrl, StatusCode, Url};
 use serde::Deserialize;
 use thiserror::Error;
 
-use crate::http::ApiError;
+use crate::{http::ApiError, metrics};
 
 #[derive(Debug, Clone, Deserialize)]
 pub struct User {
@@ -18,6 +18,9 @@ pub enum FetchUserError {
     #[error("Invalid authorization")]
     InvalidAuthorization,
 
+    #[error("Users service unavailable: {_0}")]
+    UpstreamUnavailable(#[source] anyhow::Error),
+
     #[error("Unknown: {_0}")]
     Unknown(#[from] anyhow::Error),
 }
@@ -26,14 +29,41 @@ impl From<FetchUserError> for ApiError {
     fn from(value: FetchUserError) -> Self {
         match value {
             FetchUserError::InvalidAuthorization => Self::Unauthorized(None),
+            FetchUserError::UpstreamUnavailable(e) => {
+                tracing::warn!("{:?}", e);
+                Self::ServiceUnavailable(Some("Users service unavailable".to_owned()))
+            }
             FetchUserError::Unknown(e) => Self::InternalServerError(e),
         }
     }
 }
 
+/// Extracts the token from an `Authorization` header value.
+///
+/// Both `Bearer <token>` and a bare token are accepted; any other scheme or an
+/// empty token is rejected.
+pub fn bearer_token(authorization: &str) -> Result<&str, FetchUserError> {
+    let token = match authorization.trim_start().split_once(' ') {
+        Some((scheme, token)) if scheme.eq_ignore_ascii_case("bearer") => token.trim(),
+        Some((_, rest)) if !rest.trim().is_empty() => {
+            return Err(FetchUserError::InvalidAuthorization);
+        }
+        _ => authorization.trim(),
+    };
+
+    if token.is_empty() {
+        Err(FetchUserError::InvalidAuthorization)
+    } else {
+        Ok(token)
+    }
+}
+
 #[async_trait]
 pub trait UsersApi: Send + Sync {
     async fn fetch_user(&self, authorization: &str) -> Result<Option<User>, FetchUserError>;
+
+    /// Checks that the users service can be reached, if one is used.
+    async fn ping(&self) -> anyhow::Result<()>;
 }
 
 #[derive(Debug, Clone)]
@@ -49,6 +79,12 @@ impl UsersMicroserviceClient {
             client: Client::new(),
         }
     }
+
+    /// Fails requests that take longer than `timeout`.
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.client = Client::builder().timeout(timeout).build().unwrap();
+        self
+    }
 }
 
 #[async_trait]
@@ -57,21 +93,43 @@ impl UsersApi for UsersMicroserviceClient {
         let url = self.base_url.join("/introspect").unwrap();
 
         let mut body = HashMap::new();
-        body.insert("token", authorization);
+        body.insert("token", bearer_token(authorization)?);
 
-        let res = self
-            .client
-            .post(url)
-            .json(&body)
-            .send()
+        let res = metrics::track_upstream("users", "introspect", self.client.post(url).json(&body))
+            .await
+            .map_err(|e| {
+                if e.is_connect() || e.is_timeout() {
+                    FetchUserError::UpstreamUnavailable(anyhow!(e))
+                } else {
+                    FetchUserError::Unknown(anyhow!(e))
+                }
+            })?;
+
+        match res.status() {
+            status if status.is_success() => {
+                let user = res.json().await.map_err(|e| anyhow!(e))?;
+                Ok(Some(user))
+            }
+            StatusCode::BAD_REQUEST | StatusCode::NOT_FOUND => Ok(None),
+            StatusCode::UNAUTHORIZED | StatusCode::FORBIDDEN => {
+                Err(FetchUserError::InvalidAuthorization)
+            }
+            status if status.is_server_error() || status == StatusCode::TOO_MANY_REQUESTS => Err(
+                FetchUserError::UpstreamUnavailable(anyhow!("/introspect returned {status}")),
+            ),
+            status => Err(anyhow!("Unexpected status from /introspect: {status}").into()),
+        }
+    }
+
+    async fn ping(&self) -> anyhow::Result<()> {
+        let res = metrics::track_upstream("users", "ping", self.client.get(self.base_url.clone()))
             .await
             .map_err(|e| anyhow!(e))?;
 
-        if res.status() == StatusCode::BAD_REQUEST {
-            Ok(None)
+        if res.status().is_server_error() {
+            Err(anyhow!("Users service returned {}", res.status()))
         } else {
-            let user = res.json().await.map_err(|e| anyhow!(e))?;
-            Ok(Some(user))
+            Ok(())
         }
     }
 }
@@ -90,4 +148,95 @@ impl UsersApi for MockUsersClient {
             Ok(None)
         }
     }
+
+    async fn ping(&self) -> anyhow::Result<()> {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::{Json, Router, http::StatusCode, response::IntoResponse, routing::post};
+    use serde_json::json;
+    use tokio::net::TcpListener;
+
+    use super::*;
+
+    /// Fake users service whose `/introspect` response is picked by the token.
+    async fn introspect(Json(body): Json<HashMap<String, String>>) -> impl IntoResponse {
+        match body["token"].as_str() {
+            "valid" => (StatusCode::OK, Json(json!({ "id": "42" }))).into_response(),
+            "malformed" => StatusCode::BAD_REQUEST.into_response(),
+            "expired" => StatusCode::UNAUTHORIZED.into_response(),
+            "banned" => StatusCode::FORBIDDEN.into_response(),
+            "deleted" => StatusCode::NOT_FOUND.into_response(),
+            "busy" => StatusCode::SERVICE_UNAVAILABLE.into_response(),
+            "garbage" => (StatusCode::OK, "not json").into_response(),
+            _ => StatusCode::IM_A_TEAPOT.into_response(),
+        }
+    }
+
+    async fn spawn_fake_users_service() -> UsersMicroserviceClient {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let app = Router::new().route("/introspect", post(introspect));
+
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+
+        UsersMicroserviceClient::new(format!("http://{addr}"))
+    }
+
+    #[test]
+    fn bearer_token_is_validated() {
+        assert_eq!(bearer_token("Bearer abc").unwrap(), "abc");
+        assert_eq!(bearer_token("bearer  abc ").unwrap(), "abc");
+        assert_eq!(bearer_token("abc").unwrap(), "abc");
+        assert!(bearer_token("Bearer ").is_err());
+        assert!(bearer_token("Basic abc").is_err());
+        assert!(bearer_token("").is_err());
+    }
+
+    #[tokio::test]
+    async fn fetch_user_maps_introspect_responses() {
+        let client = spawn_fake_users_service().await;
+
+        let user = client.fetch_user("Bearer valid").await.unwrap().unwrap();
+        assert_eq!(user.id, "42");
+
+        assert!(client.fetch_user("malformed").await.unwrap().is_none());
+        assert!(client.fetch_user("deleted").await.unwrap().is_none());
+        assert!(matches!(
+            client.fetch_user("expired").await,
+            Err(FetchUserError::InvalidAuthorization)
+        ));
+        assert!(matches!(
+            client.fetch_user("banned").await,
+            Err(FetchUserError::InvalidAuthorization)
+        ));
+        assert!(matches!(
+            client.fetch_user("busy").await,
+            Err(FetchUserError::UpstreamUnavailable(_))
+        ));
+        assert!(matches!(
+            client.fetch_user("garbage").await,
+            Err(FetchUserError::Unknown(_))
+        ));
+        assert!(matches!(
+            client.fetch_user("teapot").await,
+            Err(FetchUserError::Unknown(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn fetch_user_reports_unreachable_service() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        drop(listener);
+
+        let client = UsersMicroserviceClient::new(format!("http://{addr}"));
+        assert!(matches!(
+            client.fetch_user("valid").await,
+            Err(FetchUserError::UpstreamUnavailable(_))
+        ));
+    }
 }